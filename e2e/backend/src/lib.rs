@@ -1,11 +1,30 @@
 use ic_cdk::export::candid::CandidType;
+use ic_cdk::export::Principal;
 use ic_cdk_macros::*;
 use ic_evm_sign;
-use ic_evm_sign::state::{Environment, State, TransactionChainData, STATE};
+use ic_cdk::api::management_canister::http_request::HttpResponse;
+use ic_evm_sign::rpc::HttpOutcallTransport;
+use std::cell::Cell;
+use std::rc::Rc;
+use ic_evm_sign::state::{
+    ChainConfig, Environment, TransactionChainData, TransactionStatus,
+};
+use ic_evm_sign::abi::ConstructorCall;
+use ic_evm_sign::audit::AuditLogPage;
+use ic_evm_sign::types::{Address, H256};
+use ic_evm_sign::{
+    SignHashResponse, SignOptions, SignTransactionRequest, TransactionRef, ValidateTransactionResponse,
+};
+
+const RECEIPT_POLL_INTERVAL_NS: u64 = 30_000_000_000;
+
+thread_local! {
+    static LAST_RECEIPT_POLL: Cell<u64> = Cell::new(0);
+}
 
 #[derive(Debug, CandidType)]
 struct CreateAddressResponse {
-    address: String,
+    address: Address,
 }
 #[derive(Debug, CandidType)]
 struct SignTransactionResponse {
@@ -14,16 +33,34 @@ struct SignTransactionResponse {
 #[derive(Debug, CandidType)]
 struct DeployEVMContractResponse {
     tx: Vec<u8>,
+    contract_address: Address,
+}
+#[derive(Debug, CandidType)]
+struct TransferERC20Response {
+    tx: Vec<u8>,
+}
+#[derive(Debug, CandidType)]
+struct BroadcastTransactionResponse {
+    hash: H256,
 }
 #[derive(Debug, CandidType)]
 struct UserResponse {
-    address: String,
+    address: Address,
     transactions: TransactionChainData,
 }
 
 #[ic_cdk_macros::init]
 fn init(evn_opt: Option<Environment>) {
     ic_evm_sign::init(evn_opt);
+    ic_evm_sign::init_controller(ic_cdk::caller());
+    ic_evm_sign::rpc::set_transport(Rc::new(outcall_transport()));
+}
+
+fn outcall_transport() -> HttpOutcallTransport {
+    HttpOutcallTransport {
+        transform_method: Some("transform".to_string()),
+        ..Default::default()
+    }
 }
 
 #[update]
@@ -44,9 +81,11 @@ async fn create_address() -> Result<CreateAddressResponse, String> {
 async fn sign_evm_tx(
     hex_raw_tx: Vec<u8>,
     chain_id: u64,
+    options: Option<SignOptions>,
 ) -> Result<SignTransactionResponse, String> {
     let principal_id = ic_cdk::caller();
-    let res = ic_evm_sign::sign_transaction(hex_raw_tx, chain_id, principal_id)
+    let options = options.unwrap_or_default();
+    let res = ic_evm_sign::sign_transaction_with_options(hex_raw_tx, chain_id, principal_id, options)
         .await
         .map_err(|e| format!("Failed to call sign_with_ecdsa {}", e))
         .unwrap();
@@ -56,9 +95,29 @@ async fn sign_evm_tx(
     })
 }
 
+#[query]
+fn validate_evm_tx(hex_raw_tx: Vec<u8>, chain_id: u64) -> Result<ValidateTransactionResponse, String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::validate_transaction(hex_raw_tx, chain_id, principal_id)
+}
+
+#[update]
+async fn sign_evm_txs_batch(
+    requests: Vec<SignTransactionRequest>,
+) -> Vec<Result<SignTransactionResponse, String>> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::sign_transactions_batch(requests, principal_id).await;
+
+    res.into_iter()
+        .map(|r| r.map(|r| SignTransactionResponse { sign_tx: r.sign_tx }))
+        .collect()
+}
+
 #[update]
 async fn deploy_evm_contract(
     bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
     chain_id: u64,
     max_priority_fee_per_gas: u64,
     gas_limit: u64,
@@ -68,6 +127,7 @@ async fn deploy_evm_contract(
     let res = ic_evm_sign::deploy_contract(
         principal_id,
         bytecode,
+        constructor,
         chain_id,
         ic_evm_sign::u64_to_u256(max_priority_fee_per_gas),
         gas_limit,
@@ -77,7 +137,40 @@ async fn deploy_evm_contract(
     .map_err(|e| format!("Failed to call sign_with_ecdsa {}", e))
     .unwrap();
 
-    Ok(DeployEVMContractResponse { tx: res.tx })
+    Ok(DeployEVMContractResponse {
+        tx: res.tx,
+        contract_address: res.contract_address,
+    })
+}
+
+#[update]
+async fn deploy_evm_contract_create2(
+    bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
+    salt: Vec<u8>,
+    chain_id: u64,
+    max_priority_fee_per_gas: u64,
+    gas_limit: u64,
+    max_fee_per_gas: u64,
+) -> Result<DeployEVMContractResponse, String> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::deploy_contract_create2(
+        principal_id,
+        bytecode,
+        constructor,
+        salt,
+        chain_id,
+        ic_evm_sign::u64_to_u256(max_priority_fee_per_gas),
+        gas_limit,
+        ic_evm_sign::u64_to_u256(max_fee_per_gas),
+    )
+    .await
+    .map_err(|e| format!("Failed to deploy contract {}", e))?;
+
+    Ok(DeployEVMContractResponse {
+        tx: res.tx,
+        contract_address: res.contract_address,
+    })
 }
 
 #[update]
@@ -86,10 +179,10 @@ async fn transfer_erc_20(
     max_priority_fee_per_gas: u64,
     gas_limit: u64,
     max_fee_per_gas: u64,
-    address: String,
+    address: Address,
     value: u64,
-    contract_address: String,
-) -> Result<DeployEVMContractResponse, String> {
+    contract_address: Address,
+) -> Result<TransferERC20Response, String> {
     let principal_id = ic_cdk::caller();
     let res = ic_evm_sign::transfer_erc_20(
         principal_id,
@@ -105,7 +198,197 @@ async fn transfer_erc_20(
     .map_err(|e| format!("Failed to call sign_with_ecdsa {}", e))
     .unwrap();
 
-    Ok(DeployEVMContractResponse { tx: res.tx })
+    Ok(TransferERC20Response { tx: res.tx })
+}
+
+#[update]
+async fn deploy_evm_contract_with_estimated_fees(
+    bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
+    chain_id: u64,
+) -> Result<DeployEVMContractResponse, String> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::deploy_contract_with_estimated_fees(principal_id, bytecode, constructor, chain_id)
+        .await
+        .map_err(|e| format!("Failed to deploy contract {}", e))?;
+
+    Ok(DeployEVMContractResponse {
+        tx: res.tx,
+        contract_address: res.contract_address,
+    })
+}
+
+#[update]
+async fn transfer_erc_20_with_estimated_fees(
+    chain_id: u64,
+    address: Address,
+    value: u64,
+    contract_address: Address,
+) -> Result<TransferERC20Response, String> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::transfer_erc_20_with_estimated_fees(
+        principal_id,
+        chain_id,
+        address,
+        ic_evm_sign::u64_to_u256(value),
+        contract_address,
+    )
+    .await
+    .map_err(|e| format!("Failed to transfer erc 20 {}", e))?;
+
+    Ok(TransferERC20Response { tx: res.tx })
+}
+
+#[update]
+async fn replace_transaction(
+    chain_id: u64,
+    tx_ref: TransactionRef,
+    fee_bump_percent: Option<u64>,
+) -> Result<SignTransactionResponse, String> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::replace_transaction(principal_id, chain_id, tx_ref, fee_bump_percent)
+        .await
+        .map_err(|e| format!("Failed to replace transaction {}", e))?;
+
+    Ok(SignTransactionResponse { sign_tx: res.tx })
+}
+
+#[update]
+async fn cancel_transaction(
+    chain_id: u64,
+    tx_ref: TransactionRef,
+    fee_bump_percent: Option<u64>,
+) -> Result<SignTransactionResponse, String> {
+    let principal_id = ic_cdk::caller();
+    let res = ic_evm_sign::cancel_transaction(principal_id, chain_id, tx_ref, fee_bump_percent)
+        .await
+        .map_err(|e| format!("Failed to cancel transaction {}", e))?;
+
+    Ok(SignTransactionResponse { sign_tx: res.tx })
+}
+
+#[update]
+async fn sign_hash(message_hash: Vec<u8>) -> Result<SignHashResponse, String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::sign_hash(principal_id, message_hash).await
+}
+
+#[update]
+async fn set_user_raw_signing(enabled: bool) -> Result<(), String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::set_user_raw_signing(principal_id, enabled).await
+}
+
+#[update]
+async fn deposit_cycles() -> Result<u64, String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::deposit_cycles(principal_id).await
+}
+
+#[query]
+fn get_cycles_balance() -> u64 {
+    ic_evm_sign::get_cycles_balance(ic_cdk::caller())
+}
+
+#[update]
+fn set_raw_signing(enabled: bool) -> Result<(), String> {
+    ic_evm_sign::set_raw_signing(ic_cdk::caller(), enabled)
+}
+
+#[update]
+fn set_chain_config(chain_id: u64, chain_config: ChainConfig) -> Result<(), String> {
+    ic_evm_sign::set_chain_config(ic_cdk::caller(), chain_id, chain_config)
+}
+
+#[query]
+fn get_audit_log(start: u64, limit: u64) -> Result<AuditLogPage, String> {
+    ic_evm_sign::get_audit_log(ic_cdk::caller(), start, limit)
+}
+
+#[query]
+fn export_audit_log(start: u64, limit: u64) -> Result<String, String> {
+    ic_evm_sign::export_audit_log(ic_cdk::caller(), start, limit)
+}
+
+#[update]
+async fn broadcast_evm_tx(
+    chain_id: u64,
+    signed_tx: Vec<u8>,
+) -> Result<BroadcastTransactionResponse, String> {
+    let principal_id = ic_cdk::caller();
+    let transport = outcall_transport();
+    let res = ic_evm_sign::broadcast_transaction(principal_id, chain_id, signed_tx, &transport)
+        .await
+        .map_err(|e| format!("Failed to broadcast transaction {}", e))?;
+
+    Ok(BroadcastTransactionResponse { hash: res.hash })
+}
+
+#[update]
+async fn sync_nonce(chain_id: u64) -> Result<u64, String> {
+    let principal_id = ic_cdk::caller();
+    let transport = outcall_transport();
+
+    ic_evm_sign::sync_nonce(principal_id, chain_id, &transport)
+        .await
+        .map_err(|e| format!("Failed to sync nonce {}", e))
+}
+
+#[update]
+async fn get_balance(chain_id: u64) -> Result<String, String> {
+    let principal_id = ic_cdk::caller();
+    let transport = outcall_transport();
+
+    let balance = ic_evm_sign::get_balance(principal_id, chain_id, &transport)
+        .await
+        .map_err(|e| format!("Failed to get balance {}", e))?;
+
+    Ok(balance.to_string())
+}
+
+#[update]
+async fn get_erc_20_balance(chain_id: u64, contract_address: Address) -> Result<String, String> {
+    let principal_id = ic_cdk::caller();
+    let transport = outcall_transport();
+
+    let balance = ic_evm_sign::get_erc_20_balance(principal_id, chain_id, contract_address, &transport)
+        .await
+        .map_err(|e| format!("Failed to get ERC-20 balance {}", e))?;
+
+    Ok(balance.to_string())
+}
+
+#[query]
+fn get_transaction_status(chain_id: u64, hash: Vec<u8>) -> Option<TransactionStatus> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::get_transaction_status(principal_id, chain_id, &hash)
+}
+
+#[ic_cdk_macros::heartbeat]
+async fn heartbeat() {
+    let now = ic_cdk::api::time();
+    let due = LAST_RECEIPT_POLL.with(|last| {
+        if now.saturating_sub(last.get()) < RECEIPT_POLL_INTERVAL_NS {
+            return false;
+        }
+        last.set(now);
+        true
+    });
+
+    if due {
+        // Warms the root key cache so that `get_address` works as a query.
+        let _ = ic_evm_sign::get_root_key().await;
+        ic_evm_sign::poll_receipts(&outcall_transport()).await;
+    }
+}
+
+#[query]
+fn transform(response: HttpResponse) -> HttpResponse {
+    ic_evm_sign::rpc::transform(response)
 }
 
 #[update]
@@ -119,6 +402,32 @@ fn clear_caller_history(chain_id: u64) -> Result<(), String> {
     Ok(res)
 }
 
+#[update]
+fn reserve_nonce(chain_id: u64) -> Result<u64, String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::reserve_nonce(principal_id, chain_id)
+}
+
+#[update]
+fn release_nonce(chain_id: u64, nonce: u64) -> Result<(), String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::release_nonce(principal_id, chain_id, nonce)
+}
+
+#[update]
+fn resync_nonce(chain_id: u64, transaction_count: u64) -> Result<(), String> {
+    let principal_id = ic_cdk::caller();
+
+    ic_evm_sign::resync_nonce(principal_id, chain_id, transaction_count)
+}
+
+#[query]
+fn get_address(principal_id: Option<Principal>) -> Result<Address, String> {
+    ic_evm_sign::get_address(principal_id.unwrap_or_else(ic_cdk::caller))
+}
+
 #[query]
 fn get_caller_data(chain_id: u64) -> Option<UserResponse> {
     let principal_id = ic_cdk::caller();
@@ -144,16 +453,13 @@ fn export_candid() -> String {
 
 #[ic_cdk_macros::pre_upgrade]
 fn pre_upgrade() {
-    let state = STATE.with(|s| s.borrow().clone());
-
-    ic_cdk::storage::stable_save((state,)).unwrap();
+    ic_evm_sign::pre_upgrade();
 }
 
 #[ic_cdk_macros::post_upgrade]
 fn post_upgrade() {
-    let (s_prev_1,): (State,) = ic_cdk::storage::stable_restore().unwrap();
-
-    STATE.with(|s| {
-        *s.borrow_mut() = s_prev_1;
-    });
+    ic_evm_sign::post_upgrade();
+    // canisters upgraded from a release without controllers get the upgrader
+    ic_evm_sign::init_controller(ic_cdk::caller());
+    ic_evm_sign::rpc::set_transport(Rc::new(outcall_transport()));
 }
@@ -192,6 +192,7 @@ describe("Sign EVM Transactions", function () {
 
     const resDeployContract = await actor.deploy_evm_contract(
       [...bytecode],
+      [],
       chainId,
       maxPriorityFeePerGas.toNumber(),
       estimatedGasDeploy.toNumber(),
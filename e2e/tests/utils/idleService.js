@@ -26,6 +26,25 @@ const idleServiceOptions = (IDL) => {
     tx: IDL.Vec(IDL.Nat8),
   });
 
+  const abi_value = IDL.Rec();
+  abi_value.fill(
+    IDL.Variant({
+      Address: IDL.Text,
+      Uint: IDL.Text,
+      Int: IDL.Text,
+      Bool: IDL.Bool,
+      Bytes: IDL.Vec(IDL.Nat8),
+      FixedBytes: IDL.Vec(IDL.Nat8),
+      String: IDL.Text,
+      Array: IDL.Vec(abi_value),
+      Tuple: IDL.Vec(abi_value),
+    })
+  );
+  const constructor_call = IDL.Record({
+    signature: IDL.Text,
+    args: IDL.Vec(abi_value),
+  });
+
   return {
     create_address: IDL.Func(
       [],
@@ -43,7 +62,14 @@ const idleServiceOptions = (IDL) => {
       ["query"]
     ),
     deploy_evm_contract: IDL.Func(
-      [IDL.Vec(IDL.Nat8), IDL.Nat64, IDL.Nat64, IDL.Nat64, IDL.Nat64],
+      [
+        IDL.Vec(IDL.Nat8),
+        IDL.Opt(constructor_call),
+        IDL.Nat64,
+        IDL.Nat64,
+        IDL.Nat64,
+        IDL.Nat64,
+      ],
       [IDL.Variant({ Ok: deploy_response, Err: IDL.Text })],
       ["update"]
     ),
@@ -0,0 +1,405 @@
+use crate::types::Address;
+use ic_cdk::export::{candid::CandidType, serde::Deserialize};
+use primitive_types::U256;
+use std::fmt;
+
+/// Argument value for ABI encoding. Integers are decimal strings so that they
+/// can be passed over Candid without losing precision.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub enum AbiValue {
+    Address(String),
+    Uint(String),
+    Int(String),
+    Bool(bool),
+    /// Dynamic `bytes`.
+    Bytes(Vec<u8>),
+    /// `bytes1` to `bytes32`.
+    FixedBytes(Vec<u8>),
+    String(String),
+    /// Both `T[]` and `T[N]`.
+    Array(Vec<AbiValue>),
+    Tuple(Vec<AbiValue>),
+}
+
+/// Constructor signature such as `constructor(string,uint256)` and its arguments.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConstructorCall {
+    pub signature: String,
+    pub args: Vec<AbiValue>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AbiType {
+    Address,
+    Uint(usize),
+    Int(usize),
+    Bool,
+    Bytes,
+    FixedBytes(usize),
+    String,
+    Array(Box<AbiType>, Option<usize>),
+    Tuple(Vec<AbiType>),
+}
+
+impl fmt::Display for AbiType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbiType::Address => write!(f, "address"),
+            AbiType::Uint(bits) => write!(f, "uint{}", bits),
+            AbiType::Int(bits) => write!(f, "int{}", bits),
+            AbiType::Bool => write!(f, "bool"),
+            AbiType::Bytes => write!(f, "bytes"),
+            AbiType::FixedBytes(size) => write!(f, "bytes{}", size),
+            AbiType::String => write!(f, "string"),
+            AbiType::Array(inner, None) => write!(f, "{}[]", inner),
+            AbiType::Array(inner, Some(size)) => write!(f, "{}[{}]", inner, size),
+            AbiType::Tuple(types) => {
+                let types: Vec<String> = types.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", types.join(","))
+            }
+        }
+    }
+}
+
+impl AbiType {
+    fn is_dynamic(&self) -> bool {
+        match self {
+            AbiType::Bytes | AbiType::String | AbiType::Array(_, None) => true,
+            AbiType::Array(inner, Some(_)) => inner.is_dynamic(),
+            AbiType::Tuple(types) => types.iter().any(AbiType::is_dynamic),
+            _ => false,
+        }
+    }
+}
+
+/// Parses the parameter types of a signature like `constructor(address,uint256[])`.
+/// The name before the parentheses is optional.
+pub fn parse_signature(signature: &str) -> Result<Vec<AbiType>, String> {
+    let signature = signature.trim();
+    let start = signature
+        .find('(')
+        .ok_or_else(|| format!("Invalid signature {}", signature))?;
+    match parse_type(&signature[start..])? {
+        AbiType::Tuple(types) => Ok(types),
+        _ => Err(format!("Invalid signature {}", signature)),
+    }
+}
+
+pub fn parse_type(type_str: &str) -> Result<AbiType, String> {
+    let type_str = type_str.trim();
+    let invalid = || format!("Invalid ABI type {}", type_str);
+
+    if let Some(rest) = type_str.strip_suffix(']') {
+        let open = rest.rfind('[').ok_or_else(invalid)?;
+        let inner = parse_type(&rest[..open])?;
+        let size = match &rest[open + 1..] {
+            "" => None,
+            size => Some(size.parse::<usize>().map_err(|_| invalid())?),
+        };
+        return Ok(AbiType::Array(Box::new(inner), size));
+    }
+
+    if let Some(rest) = type_str.strip_prefix('(') {
+        let rest = rest.strip_suffix(')').ok_or_else(invalid)?;
+        if rest.trim().is_empty() {
+            return Ok(AbiType::Tuple(vec![]));
+        }
+        let types = split_top_level(rest)
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(parse_type)
+            .collect::<Result<Vec<AbiType>, String>>()?;
+        return Ok(AbiType::Tuple(types));
+    }
+
+    match type_str {
+        "address" => Ok(AbiType::Address),
+        "bool" => Ok(AbiType::Bool),
+        "string" => Ok(AbiType::String),
+        "bytes" => Ok(AbiType::Bytes),
+        "uint" => Ok(AbiType::Uint(256)),
+        "int" => Ok(AbiType::Int(256)),
+        _ => {
+            if let Some(bits) = type_str.strip_prefix("uint") {
+                let bits = bits.parse::<usize>().map_err(|_| invalid())?;
+                if bits == 0 || bits > 256 || bits % 8 != 0 {
+                    return Err(invalid());
+                }
+                Ok(AbiType::Uint(bits))
+            } else if let Some(bits) = type_str.strip_prefix("int") {
+                let bits = bits.parse::<usize>().map_err(|_| invalid())?;
+                if bits == 0 || bits > 256 || bits % 8 != 0 {
+                    return Err(invalid());
+                }
+                Ok(AbiType::Int(bits))
+            } else if let Some(size) = type_str.strip_prefix("bytes") {
+                let size = size.parse::<usize>().map_err(|_| invalid())?;
+                if size == 0 || size > 32 {
+                    return Err(invalid());
+                }
+                Ok(AbiType::FixedBytes(size))
+            } else {
+                Err(invalid())
+            }
+        }
+    }
+}
+
+// Splits on commas that are not nested in parentheses.
+fn split_top_level(types: &str) -> Option<Vec<&str>> {
+    let mut parts = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in types.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                parts.push(&types[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    parts.push(&types[start..]);
+    Some(parts)
+}
+
+/// ABI-encodes `args` as the parameters of `signature`.
+pub fn encode_args(signature: &str, args: &[AbiValue]) -> Result<Vec<u8>, String> {
+    let types = parse_signature(signature)?;
+    if types.len() != args.len() {
+        return Err(format!("Expected {} arguments, got {}", types.len(), args.len()));
+    }
+    encode_tuple(&types, args)
+}
+
+fn encode_tuple(types: &[AbiType], values: &[AbiValue]) -> Result<Vec<u8>, String> {
+    let mut heads = vec![];
+    let mut tails = vec![];
+    let head_size = tuple_head_size(types)?;
+
+    for (abi_type, value) in types.iter().zip(values) {
+        let encoded = encode_value(abi_type, value)?;
+        if abi_type.is_dynamic() {
+            heads.push(encode_usize(head_size + tails.len()));
+            tails.extend(encoded);
+        } else {
+            heads.push(encoded);
+        }
+    }
+
+    let mut result = heads.concat();
+    result.extend(tails);
+    Ok(result)
+}
+
+// Size taken by a value in the head of its enclosing tuple.
+fn head_size(abi_type: &AbiType) -> Result<usize, String> {
+    match abi_type {
+        _ if abi_type.is_dynamic() => Ok(32),
+        AbiType::Array(inner, Some(size)) => head_size(inner)?
+            .checked_mul(*size)
+            .ok_or_else(|| format!("{} is too large to encode", abi_type)),
+        AbiType::Tuple(types) => tuple_head_size(types),
+        _ => Ok(32),
+    }
+}
+
+fn tuple_head_size(types: &[AbiType]) -> Result<usize, String> {
+    types.iter().try_fold(0usize, |total, abi_type| {
+        total
+            .checked_add(head_size(abi_type)?)
+            .ok_or_else(|| format!("{} is too large to encode", abi_type))
+    })
+}
+
+fn encode_value(abi_type: &AbiType, value: &AbiValue) -> Result<Vec<u8>, String> {
+    let invalid = || format!("Invalid value for {}", abi_type);
+
+    match (abi_type, value) {
+        (AbiType::Address, AbiValue::Address(address)) => {
+            let bytes = address.parse::<Address>().map_err(|_| invalid())?.0;
+            Ok(pad_left(&bytes))
+        }
+        (AbiType::Uint(bits), AbiValue::Uint(number)) => {
+            let number = U256::from_dec_str(number).map_err(|_| invalid())?;
+            if *bits < 256 && number >> *bits != U256::zero() {
+                return Err(invalid());
+            }
+            Ok(encode_u256(number))
+        }
+        (AbiType::Int(bits), AbiValue::Int(number)) => {
+            let (negative, magnitude) = match number.strip_prefix('-') {
+                Some(magnitude) => (true, magnitude),
+                None => (false, number.as_str()),
+            };
+            let magnitude = U256::from_dec_str(magnitude).map_err(|_| invalid())?;
+            let limit = U256::one() << (*bits - 1);
+            if (negative && magnitude > limit) || (!negative && magnitude >= limit) {
+                return Err(invalid());
+            }
+            if negative {
+                Ok(encode_u256((!magnitude).overflowing_add(U256::one()).0))
+            } else {
+                Ok(encode_u256(magnitude))
+            }
+        }
+        (AbiType::Bool, AbiValue::Bool(b)) => Ok(encode_usize(*b as usize)),
+        (AbiType::FixedBytes(size), AbiValue::FixedBytes(bytes)) => {
+            if bytes.len() != *size {
+                return Err(invalid());
+            }
+            Ok(pad_right(bytes))
+        }
+        (AbiType::Bytes, AbiValue::Bytes(bytes)) => Ok(encode_dynamic_bytes(bytes)),
+        (AbiType::String, AbiValue::String(string)) => Ok(encode_dynamic_bytes(string.as_bytes())),
+        (AbiType::Array(inner, size), AbiValue::Array(values)) => {
+            let types = vec![inner.as_ref().clone(); values.len()];
+            match size {
+                Some(size) if values.len() != *size => Err(invalid()),
+                Some(_) => encode_tuple(&types, values),
+                None => {
+                    let mut result = encode_usize(values.len());
+                    result.extend(encode_tuple(&types, values)?);
+                    Ok(result)
+                }
+            }
+        }
+        (AbiType::Tuple(types), AbiValue::Tuple(values)) => {
+            if types.len() != values.len() {
+                return Err(invalid());
+            }
+            encode_tuple(types, values)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn encode_u256(value: U256) -> Vec<u8> {
+    let mut bytes = [0; 32];
+    value.to_big_endian(&mut bytes);
+    bytes.to_vec()
+}
+
+fn encode_usize(value: usize) -> Vec<u8> {
+    encode_u256(U256::from(value))
+}
+
+fn encode_dynamic_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut result = encode_usize(bytes.len());
+    for chunk in bytes.chunks(32) {
+        result.extend(pad_right(chunk));
+    }
+    result
+}
+
+fn pad_left(bytes: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; 32 - bytes.len()];
+    result.extend(bytes);
+    result
+}
+
+fn pad_right(bytes: &[u8]) -> Vec<u8> {
+    let mut result = bytes.to_vec();
+    result.resize(32, 0);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{string_to_vec_u8, vec_u8_to_string};
+
+    #[test]
+    fn parse_signature_nested() {
+        let types = parse_signature("constructor(address,uint[],(bytes32,bool)[2])").unwrap();
+        assert_eq!(
+            types,
+            vec![
+                AbiType::Address,
+                AbiType::Array(Box::new(AbiType::Uint(256)), None),
+                AbiType::Array(
+                    Box::new(AbiType::Tuple(vec![AbiType::FixedBytes(32), AbiType::Bool])),
+                    Some(2)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_signature_invalid_type() {
+        assert_eq!(parse_signature("constructor(uint7)"), Err("Invalid ABI type uint7".to_string()));
+        assert_eq!(parse_signature("constructor"), Err("Invalid signature constructor".to_string()));
+    }
+
+    #[test]
+    fn encode_static_args() {
+        let args = vec![
+            AbiValue::Uint("69".to_string()),
+            AbiValue::Bool(true),
+        ];
+        let expected = "0000000000000000000000000000000000000000000000000000000000000045\
+                        0000000000000000000000000000000000000000000000000000000000000001";
+        let result = encode_args("baz(uint32,bool)", &args).unwrap();
+        assert_eq!(vec_u8_to_string(&result), expected);
+    }
+
+    #[test]
+    fn encode_dynamic_args() {
+        // Example from the Solidity ABI specification for f(uint256,uint32[],bytes10,bytes)
+        let args = vec![
+            AbiValue::Uint("291".to_string()),
+            AbiValue::Array(vec![
+                AbiValue::Uint("1110".to_string()),
+                AbiValue::Uint("1929".to_string()),
+            ]),
+            AbiValue::FixedBytes(b"1234567890".to_vec()),
+            AbiValue::Bytes(b"Hello, world!".to_vec()),
+        ];
+        let expected = string_to_vec_u8(
+            "0000000000000000000000000000000000000000000000000000000000000123\
+             0000000000000000000000000000000000000000000000000000000000000080\
+             3132333435363738393000000000000000000000000000000000000000000000\
+             00000000000000000000000000000000000000000000000000000000000000e0\
+             0000000000000000000000000000000000000000000000000000000000000002\
+             0000000000000000000000000000000000000000000000000000000000000456\
+             0000000000000000000000000000000000000000000000000000000000000789\
+             000000000000000000000000000000000000000000000000000000000000000d\
+             48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        );
+        let result = encode_args("f(uint256,uint32[],bytes10,bytes)", &args).unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn encode_negative_int() {
+        let result = encode_args("(int8)", &[AbiValue::Int("-1".to_string())]).unwrap();
+        assert_eq!(result, vec![0xff; 32]);
+
+        let result = encode_args("(int8)", &[AbiValue::Int("-129".to_string())]);
+        assert_eq!(result, Err("Invalid value for int8".to_string()));
+    }
+
+    #[test]
+    fn encode_invalid_args() {
+        let result = encode_args("(uint8)", &[AbiValue::Uint("256".to_string())]);
+        assert_eq!(result, Err("Invalid value for uint8".to_string()));
+
+        let result = encode_args("(address)", &[AbiValue::Bool(true)]);
+        assert_eq!(result, Err("Invalid value for address".to_string()));
+
+        let result = encode_args("(address,bool)", &[AbiValue::Bool(true)]);
+        assert_eq!(result, Err("Expected 2 arguments, got 1".to_string()));
+    }
+
+    #[test]
+    fn encode_oversized_fixed_array() {
+        let signature = format!("(uint8[{}][4])", usize::MAX / 64);
+        let result = encode_args(&signature, &[AbiValue::Array(vec![])]);
+        assert_eq!(result, Err(format!("uint8[{}][4] is too large to encode", usize::MAX / 64)));
+    }
+}
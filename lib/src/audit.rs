@@ -0,0 +1,294 @@
+use crate::types::Address;
+use crate::{stable64_grow, stable64_read, stable64_size, stable64_write};
+use ic_cdk::export::{
+    candid::{CandidType, Decode, Encode},
+    serde::{Deserialize, Serialize},
+    Principal,
+};
+use std::cell::RefCell;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AuditOperation {
+    SignTransaction,
+    VerifyTransaction,
+    DeployContract,
+    TransferErc20,
+    ReplaceTransaction,
+    CancelTransaction,
+    SignHash,
+    SetUserRawSigning { enabled: bool },
+    DepositCycles { amount: u64 },
+    ClearCallerHistory,
+    AddController { principal: Principal },
+    SetChainConfig,
+    SetRawSigning { enabled: bool },
+    ReleaseNonce { nonce: u64 },
+    MarkNonceBroadcast { nonce: u64 },
+    ConfirmNonce { nonce: u64 },
+    ResyncNonce { transaction_count: u64 },
+    SyncNonce,
+    /// `nonce` is `None` when the reservation failed.
+    ReserveNonce { nonce: Option<u64> },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub caller: Principal,
+    /// Address of the caller's key, when it is known.
+    pub account: Option<Address>,
+    pub chain_id: Option<u64>,
+    pub operation: AuditOperation,
+    /// Hash of the signed transaction, or the digest signed by `sign_hash`.
+    pub hash: Option<Vec<u8>>,
+    /// Cycles spent on `sign_with_ecdsa`.
+    pub cycles: u64,
+    pub outcome: Result<(), String>,
+}
+
+#[derive(CandidType, Deserialize, Debug)]
+pub struct AuditLogPage {
+    pub entries: Vec<AuditEntry>,
+    pub total: u64,
+    /// Entries that could not be written since the last upgrade.
+    pub dropped: u64,
+}
+
+/// Largest number of entries returned by one call.
+pub const MAX_AUDIT_PAGE_SIZE: u64 = 500;
+
+// Stable memory starts with a header followed by the entries, each prefixed
+// with its length. On upgrade the state is written after the last entry and
+// overwritten by the next entries once it was restored.
+const MAGIC: &[u8; 8] = b"EVMAUDIT";
+const HEADER_SIZE: u64 = 24;
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+thread_local! {
+    // Offset of every entry, rebuilt from stable memory after an upgrade.
+    static OFFSETS: RefCell<Vec<u64>> = const { RefCell::new(vec![]) };
+    // Entries that failed to append; the log is best effort, see `audit` in lib.rs.
+    static DROPPED: RefCell<u64> = const { RefCell::new(0) };
+}
+
+pub(crate) fn append(entry: &AuditEntry) -> Result<(), String> {
+    let bytes = Encode!(entry).map_err(|e| format!("failed to encode audit entry {}", e))?;
+    let (count, end) = read_header().unwrap_or((0, HEADER_SIZE));
+
+    write(end, &(bytes.len() as u32).to_le_bytes())?;
+    write(end + 4, &bytes)?;
+    write_header(count + 1, end + 4 + bytes.len() as u64)?;
+
+    OFFSETS.with(|o| o.borrow_mut().push(end));
+    Ok(())
+}
+
+pub fn len() -> u64 {
+    OFFSETS.with(|o| o.borrow().len() as u64)
+}
+
+pub(crate) fn record_dropped() {
+    DROPPED.with(|d| *d.borrow_mut() += 1);
+}
+
+pub fn dropped() -> u64 {
+    DROPPED.with(|d| *d.borrow())
+}
+
+/// Returns up to `limit` entries starting at index `start`, oldest first.
+pub(crate) fn read_entries(start: u64, limit: u64) -> Result<Vec<AuditEntry>, String> {
+    let offsets = OFFSETS.with(|o| o.borrow().clone());
+    let limit = limit.min(MAX_AUDIT_PAGE_SIZE);
+
+    offsets
+        .iter()
+        .skip(start as usize)
+        .take(limit as usize)
+        .map(|offset| {
+            let bytes = read_blob(*offset);
+            Decode!(&bytes, AuditEntry).map_err(|e| format!("failed to decode audit entry {}", e))
+        })
+        .collect()
+}
+
+/// Exports a page of entries as CSV, with a header line.
+pub(crate) fn export_csv(start: u64, limit: u64) -> Result<String, String> {
+    let mut csv = "timestamp,caller,account,chain_id,operation,hash,cycles,outcome\n".to_string();
+    for entry in read_entries(start, limit)? {
+        let fields = [
+            entry.timestamp.to_string(),
+            entry.caller.to_text(),
+            entry.account.map(|a| a.to_string()).unwrap_or_default(),
+            entry.chain_id.map(|c| c.to_string()).unwrap_or_default(),
+            format!("{:?}", entry.operation),
+            entry.hash.map(|h| format!("0x{}", crate::utils::vec_u8_to_string(&h))).unwrap_or_default(),
+            entry.cycles.to_string(),
+            match entry.outcome {
+                Ok(()) => "ok".to_string(),
+                Err(e) => format!("error: {}", e),
+            },
+        ];
+        let line = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+        csv.push_str(&line);
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+/// Writes `state` after the last entry, for [`restore_state`] to read after
+/// an upgrade.
+pub(crate) fn save_state(state: &[u8]) -> Result<(), String> {
+    let (count, end) = read_header().unwrap_or((0, HEADER_SIZE));
+    write_header(count, end)?;
+    write(end, &(state.len() as u64).to_le_bytes())?;
+    write(end + 8, state)
+}
+
+/// Reloads the entry offsets and returns the state saved by [`save_state`],
+/// or `None` when stable memory does not hold a log yet.
+pub(crate) fn restore_state() -> Result<Option<Vec<u8>>, String> {
+    let (count, end) = match read_header() {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    let mut offsets = Vec::with_capacity(count as usize);
+    let mut offset = HEADER_SIZE;
+    for _ in 0..count {
+        offsets.push(offset);
+        let mut len = [0; 4];
+        stable64_read(offset, &mut len);
+        offset += 4 + u32::from_le_bytes(len) as u64;
+    }
+    if offset != end {
+        return Err("audit log is corrupted".to_string());
+    }
+    OFFSETS.with(|o| *o.borrow_mut() = offsets);
+
+    let mut len = [0; 8];
+    stable64_read(end, &mut len);
+    let mut state = vec![0; u64::from_le_bytes(len) as usize];
+    stable64_read(end + 8, &mut state);
+    Ok(Some(state))
+}
+
+/// Returns all of stable memory, for states saved before the log existed.
+pub(crate) fn read_legacy_state() -> Vec<u8> {
+    let mut bytes = vec![0; (stable64_size() * WASM_PAGE_SIZE) as usize];
+    stable64_read(0, &mut bytes);
+    bytes
+}
+
+/// Starts an empty log, overwriting whatever stable memory holds.
+pub(crate) fn clear() -> Result<(), String> {
+    write_header(0, HEADER_SIZE)?;
+    OFFSETS.with(|o| o.borrow_mut().clear());
+    Ok(())
+}
+
+fn read_header() -> Option<(u64, u64)> {
+    if stable64_size() == 0 {
+        return None;
+    }
+    let mut header = [0; HEADER_SIZE as usize];
+    stable64_read(0, &mut header);
+    if &header[..8] != MAGIC {
+        return None;
+    }
+    let count = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let end = u64::from_le_bytes(header[16..24].try_into().unwrap());
+    Some((count, end))
+}
+
+fn write_header(count: u64, end: u64) -> Result<(), String> {
+    let header = [&MAGIC[..], &count.to_le_bytes(), &end.to_le_bytes()].concat();
+    write(0, &header)
+}
+
+fn read_blob(offset: u64) -> Vec<u8> {
+    let mut len = [0; 4];
+    stable64_read(offset, &mut len);
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    stable64_read(offset + 4, &mut bytes);
+    bytes
+}
+
+fn write(offset: u64, bytes: &[u8]) -> Result<(), String> {
+    let required = offset + bytes.len() as u64;
+    let size = stable64_size() * WASM_PAGE_SIZE;
+    if required > size {
+        let pages = (required - size).div_ceil(WASM_PAGE_SIZE);
+        stable64_grow(pages).map_err(|e| format!("failed to grow stable memory {}", e))?;
+    }
+    stable64_write(offset, bytes);
+    Ok(())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(operation: AuditOperation, outcome: Result<(), String>) -> AuditEntry {
+        AuditEntry {
+            timestamp: 1,
+            caller: Principal::from_text("aaaaa-aa").unwrap(),
+            account: None,
+            chain_id: Some(1),
+            operation,
+            hash: Some(vec![0xab]),
+            cycles: 10,
+            outcome,
+        }
+    }
+
+    #[test]
+    fn append_and_read() {
+        append(&entry(AuditOperation::SignTransaction, Ok(()))).unwrap();
+        append(&entry(AuditOperation::SignHash, Err("failed".to_string()))).unwrap();
+
+        assert_eq!(len(), 2);
+        let entries = read_entries(1, 10).unwrap();
+        assert_eq!(entries, vec![entry(AuditOperation::SignHash, Err("failed".to_string()))]);
+        assert!(read_entries(2, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn state_survives_restore() {
+        append(&entry(AuditOperation::SignTransaction, Ok(()))).unwrap();
+        save_state(b"state").unwrap();
+        OFFSETS.with(|o| o.borrow_mut().clear());
+
+        assert_eq!(restore_state(), Ok(Some(b"state".to_vec())));
+        assert_eq!(len(), 1);
+
+        // the saved state is overwritten by the next entries
+        append(&entry(AuditOperation::SignHash, Ok(()))).unwrap();
+        assert_eq!(read_entries(0, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn restore_without_log() {
+        assert_eq!(restore_state(), Ok(None));
+    }
+
+    #[test]
+    fn export_quotes_fields() {
+        append(&entry(AuditOperation::SetRawSigning { enabled: true }, Err("a, \"b\"".to_string()))).unwrap();
+
+        let csv = export_csv(0, 10).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "timestamp,caller,account,chain_id,operation,hash,cycles,outcome");
+        assert_eq!(
+            lines[1],
+            "1,aaaaa-aa,,1,SetRawSigning { enabled: true },0xab,10,\"error: a, \"\"b\"\"\""
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::transaction::{Sign, Transaction1559, Transaction2930, TransactionLegacy, TransactionType};
+use crate::types::{AccessListItem, Address, Bytes};
+use primitive_types::U256;
+
+/// Builds unsigned transactions from typed fields.
+///
+/// The transaction type is inferred from the fee fields unless it is set with
+/// [`transaction_type`](TransactionBuilder::transaction_type): EIP-1559 when
+/// the max fees are set, EIP-2930 when an access list is given and legacy
+/// otherwise. Leaving `to` unset creates a contract.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionBuilder {
+    chain_id: u64,
+    nonce: u64,
+    to: Option<Address>,
+    value: U256,
+    data: Bytes,
+    gas_limit: u64,
+    gas_price: Option<U256>,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    access_list: Vec<AccessListItem>,
+    transaction_type: Option<TransactionType>,
+}
+
+impl TransactionBuilder {
+    pub fn new(chain_id: u64) -> Self {
+        TransactionBuilder {
+            chain_id,
+            ..Default::default()
+        }
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn value(mut self, value: U256) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn data(mut self, data: Bytes) -> Self {
+        self.data = data;
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    pub fn gas_price(mut self, gas_price: U256) -> Self {
+        self.gas_price = Some(gas_price);
+        self
+    }
+
+    pub fn max_fee_per_gas(mut self, max_fee_per_gas: U256) -> Self {
+        self.max_fee_per_gas = Some(max_fee_per_gas);
+        self
+    }
+
+    pub fn max_priority_fee_per_gas(mut self, max_priority_fee_per_gas: U256) -> Self {
+        self.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+        self
+    }
+
+    pub fn access_list(mut self, access_list: Vec<AccessListItem>) -> Self {
+        self.access_list = access_list;
+        self
+    }
+
+    pub fn transaction_type(mut self, transaction_type: TransactionType) -> Self {
+        self.transaction_type = Some(transaction_type);
+        self
+    }
+
+    /// Validates the fields and returns the unsigned transaction.
+    pub fn build(&self) -> Result<Box<dyn Sign>, String> {
+        if self.chain_id == 0 {
+            return Err("Chain id is required".to_string());
+        }
+        if self.gas_limit == 0 {
+            return Err("Gas limit is required".to_string());
+        }
+        if self.to.is_none() && self.data.is_empty() {
+            return Err("Contract creation requires data".to_string());
+        }
+
+        match self.get_transaction_type() {
+            TransactionType::Legacy => {
+                if !self.access_list.is_empty() {
+                    return Err("Legacy transactions do not support access lists".to_string());
+                }
+                Ok(Box::new(TransactionLegacy {
+                    chain_id: self.chain_id,
+                    nonce: self.nonce,
+                    gas_price: self.get_gas_price()?,
+                    gas_limit: self.gas_limit,
+                    to: self.to,
+                    value: self.value,
+                    data: self.data.clone(),
+                    ..Default::default()
+                }))
+            }
+            TransactionType::EIP2930 => Ok(Box::new(Transaction2930 {
+                chain_id: self.chain_id,
+                nonce: self.nonce,
+                gas_price: self.get_gas_price()?,
+                gas_limit: self.gas_limit,
+                to: self.to,
+                value: self.value,
+                data: self.data.clone(),
+                access_list: self.access_list.clone(),
+                ..Default::default()
+            })),
+            TransactionType::EIP1559 => {
+                if self.gas_price.is_some() {
+                    return Err("EIP-1559 transactions do not support gas price".to_string());
+                }
+                let max_fee_per_gas = self.max_fee_per_gas.ok_or("Max fee per gas is required")?;
+                let max_priority_fee_per_gas = self
+                    .max_priority_fee_per_gas
+                    .ok_or("Max priority fee per gas is required")?;
+                if max_priority_fee_per_gas > max_fee_per_gas {
+                    return Err("Max priority fee per gas exceeds max fee per gas".to_string());
+                }
+                Ok(Box::new(Transaction1559 {
+                    chain_id: self.chain_id,
+                    nonce: self.nonce,
+                    max_priority_fee_per_gas,
+                    gas_limit: self.gas_limit,
+                    max_fee_per_gas,
+                    to: self.to,
+                    value: self.value,
+                    data: self.data.clone(),
+                    access_list: self.access_list.clone(),
+                    ..Default::default()
+                }))
+            }
+        }
+    }
+
+    /// Same as [`build`](TransactionBuilder::build) but returns the raw
+    /// transaction expected by `sign_transaction`.
+    pub fn build_raw(&self) -> Result<Vec<u8>, String> {
+        self.build()?.serialize()
+    }
+
+    fn get_transaction_type(&self) -> TransactionType {
+        match self.transaction_type {
+            Some(transaction_type) => transaction_type,
+            None if self.max_fee_per_gas.is_some() || self.max_priority_fee_per_gas.is_some() => {
+                TransactionType::EIP1559
+            }
+            None if !self.access_list.is_empty() => TransactionType::EIP2930,
+            None => TransactionType::Legacy,
+        }
+    }
+
+    fn get_gas_price(&self) -> Result<U256, String> {
+        if self.max_fee_per_gas.is_some() || self.max_priority_fee_per_gas.is_some() {
+            return Err("Only EIP-1559 transactions support max fees".to_string());
+        }
+        self.gas_price.ok_or_else(|| "Gas price is required".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::get_transaction;
+    use crate::types::H256;
+
+    fn address() -> Address {
+        "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap()
+    }
+
+    #[test]
+    fn build_infers_transaction_type() {
+        let base = TransactionBuilder::new(1).to(address()).gas_limit(21_000);
+        let access_list = vec![AccessListItem {
+            address: address(),
+            storage_keys: vec![H256([1; 32])],
+        }];
+
+        let legacy = base.clone().gas_price(U256::from(10)).build_raw().unwrap();
+        let eip2930 = base.clone().gas_price(U256::from(10)).access_list(access_list).build_raw().unwrap();
+        let eip1559 = base
+            .max_fee_per_gas(U256::from(20))
+            .max_priority_fee_per_gas(U256::from(2))
+            .build_raw()
+            .unwrap();
+
+        assert!(legacy[0] >= 0xc0);
+        assert_eq!(eip2930[0], 0x01);
+        assert_eq!(eip1559[0], 0x02);
+    }
+
+    #[test]
+    fn build_round_trips_fields() {
+        let raw_tx = TransactionBuilder::new(5)
+            .nonce(3)
+            .to(address())
+            .value(U256::from(7))
+            .data("0xa9059cbb".parse().unwrap())
+            .gas_limit(50_000)
+            .max_fee_per_gas(U256::from(20))
+            .max_priority_fee_per_gas(U256::from(2))
+            .build_raw()
+            .unwrap();
+
+        let tx = get_transaction(&raw_tx, 5).unwrap();
+        assert!(!tx.is_signed());
+        assert_eq!(tx.get_nonce(), Ok(3));
+
+        let tx = Transaction1559::try_from(raw_tx).unwrap();
+        assert_eq!(tx.chain_id, 5);
+        assert_eq!(tx.to, Some(address()));
+        assert_eq!(tx.data, "0xa9059cbb".parse().unwrap());
+        assert_eq!(tx.value, U256::from(7));
+    }
+
+    #[test]
+    fn build_contract_creation() {
+        let builder = TransactionBuilder::new(1).gas_limit(100_000).gas_price(U256::one());
+        assert_eq!(builder.build_raw().err(), Some("Contract creation requires data".to_string()));
+
+        let raw_tx = builder.data(Bytes(vec![0x60, 0x80])).build_raw().unwrap();
+        assert_eq!(TransactionLegacy::try_from((raw_tx, 1)).unwrap().to, None);
+    }
+
+    #[test]
+    fn build_invalid_fees() {
+        let base = TransactionBuilder::new(1).to(address()).gas_limit(21_000);
+
+        assert_eq!(base.build().err(), Some("Gas price is required".to_string()));
+        assert_eq!(
+            base.clone().max_fee_per_gas(U256::from(20)).build().err(),
+            Some("Max priority fee per gas is required".to_string())
+        );
+        assert_eq!(
+            base.clone()
+                .max_fee_per_gas(U256::from(1))
+                .max_priority_fee_per_gas(U256::from(2))
+                .build()
+                .err(),
+            Some("Max priority fee per gas exceeds max fee per gas".to_string())
+        );
+        assert_eq!(
+            base.clone()
+                .gas_price(U256::one())
+                .max_fee_per_gas(U256::one())
+                .transaction_type(TransactionType::Legacy)
+                .build()
+                .err(),
+            Some("Only EIP-1559 transactions support max fees".to_string())
+        );
+        assert_eq!(
+            TransactionBuilder::new(1).to(address()).gas_price(U256::one()).build().err(),
+            Some("Gas limit is required".to_string())
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::state::ExtendedPublicKey;
+use hmac::{Hmac, Mac, NewMac};
+use libsecp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+
+/// Derives the public key of `derivation_path` below `key` the way the IC
+/// derives threshold ECDSA keys, i.e. BIP-32 style non-hardened derivation
+/// where each path element is used as the index.
+///
+/// Deriving from the key returned by `ecdsa_public_key` with an empty path
+/// gives the same key as calling `ecdsa_public_key` with `derivation_path`.
+pub fn derive_public_key(key: &ExtendedPublicKey, derivation_path: &[Vec<u8>]) -> Result<ExtendedPublicKey, String> {
+    let mut public_key = parse_public_key(&key.public_key)?;
+    let mut chain_code = parse_chain_code(&key.chain_code)?;
+
+    for index in derivation_path {
+        let (offset, next_chain_code) = derive_offset(&public_key, &chain_code, index);
+        if let Some(offset) = offset {
+            public_key
+                .tweak_add_assign(&offset)
+                .map_err(|_| "Invalid derivation".to_string())?;
+        }
+        chain_code = next_chain_code;
+    }
+
+    Ok(ExtendedPublicKey {
+        public_key: public_key.serialize_compressed().to_vec(),
+        chain_code: chain_code.to_vec(),
+    })
+}
+
+/// Same derivation applied to a secret key, so that its public key matches
+/// [`derive_public_key`].
+pub fn derive_secret_key(
+    secret_key: &SecretKey,
+    chain_code: &[u8],
+    derivation_path: &[Vec<u8>],
+) -> Result<SecretKey, String> {
+    let mut secret_key = *secret_key;
+    let mut chain_code = parse_chain_code(chain_code)?;
+
+    for index in derivation_path {
+        let public_key = PublicKey::from_secret_key(&secret_key);
+        let (offset, next_chain_code) = derive_offset(&public_key, &chain_code, index);
+        if let Some(offset) = offset {
+            secret_key
+                .tweak_add_assign(&offset)
+                .map_err(|_| "Invalid derivation".to_string())?;
+        }
+        chain_code = next_chain_code;
+    }
+
+    Ok(secret_key)
+}
+
+// Returns the scalar added to the key, `None` for zero, and the next chain code.
+// When the HMAC output is not a valid offset, the derivation is retried with
+// `0x01 || chain code || index` as SLIP-10 describes.
+fn derive_offset(public_key: &PublicKey, chain_code: &[u8; 32], index: &[u8]) -> (Option<SecretKey>, [u8; 32]) {
+    let mut input = public_key.serialize_compressed().to_vec();
+
+    loop {
+        input.extend_from_slice(index);
+        let mut hmac = Hmac::<Sha512>::new_varkey(chain_code).expect("HMAC accepts keys of any size");
+        hmac.update(&input);
+        let output = hmac.finalize().into_bytes();
+
+        let mut next_chain_code = [0; 32];
+        next_chain_code.copy_from_slice(&output[32..]);
+
+        if output[..32].iter().all(|&b| b == 0) {
+            return (None, next_chain_code);
+        }
+        if let Ok(offset) = SecretKey::parse_slice(&output[..32]) {
+            let mut next_key = *public_key;
+            if next_key.tweak_add_assign(&offset).is_ok() {
+                return (Some(offset), next_chain_code);
+            }
+        }
+
+        input.truncate(0);
+        input.push(1);
+        input.extend_from_slice(&next_chain_code);
+    }
+}
+
+fn parse_public_key(public_key: &[u8]) -> Result<PublicKey, String> {
+    PublicKey::parse_slice(public_key, None).map_err(|_| "Invalid public key".to_string())
+}
+
+fn parse_chain_code(chain_code: &[u8]) -> Result<[u8; 32], String> {
+    chain_code.try_into().map_err(|_| "Invalid chain code".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::string_to_vec_u8;
+
+    fn root_key() -> (SecretKey, ExtendedPublicKey) {
+        let secret_key = SecretKey::parse_slice(&[7; 32]).unwrap();
+        let key = ExtendedPublicKey {
+            public_key: PublicKey::from_secret_key(&secret_key).serialize_compressed().to_vec(),
+            chain_code: vec![1; 32],
+        };
+        (secret_key, key)
+    }
+
+    #[test]
+    fn derive_public_key_matches_secret_key() {
+        let (secret_key, key) = root_key();
+        let path = vec![vec![1, 2, 3], vec![], vec![4; 40]];
+
+        let derived = derive_public_key(&key, &path).unwrap();
+        let derived_secret = derive_secret_key(&secret_key, &key.chain_code, &path).unwrap();
+
+        assert_ne!(derived.public_key, key.public_key);
+        assert_eq!(
+            derived.public_key,
+            PublicKey::from_secret_key(&derived_secret).serialize_compressed().to_vec()
+        );
+    }
+
+    #[test]
+    fn derive_public_key_is_incremental() {
+        let (_, key) = root_key();
+        let first = derive_public_key(&key, &[vec![1]]).unwrap();
+        let both = derive_public_key(&key, &[vec![1], vec![2]]).unwrap();
+        assert_eq!(derive_public_key(&first, &[vec![2]]).unwrap(), both);
+        assert_eq!(derive_public_key(&key, &[]).unwrap(), key);
+    }
+
+    // BIP32 test vector 1, public derivation of m/0H/1 from m/0H. A path
+    // element of four bytes is the big-endian child index.
+    #[test]
+    fn derive_public_key_known_vector() {
+        let key = ExtendedPublicKey {
+            public_key: string_to_vec_u8("035a784662a4a20a65bf6aab9ae98a6c068a81c52e4b032c0fb5400c706cfccc56"),
+            chain_code: string_to_vec_u8("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141"),
+        };
+        let derived = derive_public_key(&key, &[vec![0, 0, 0, 1]]).unwrap();
+
+        assert_eq!(
+            derived.public_key,
+            string_to_vec_u8("03501e454bf00751f24b1b489aa925215d66af2234e3891c3b21a52bedb3cd711c")
+        );
+        assert_eq!(
+            derived.chain_code,
+            string_to_vec_u8("2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19")
+        );
+    }
+
+    #[test]
+    fn derive_public_key_invalid_chain_code() {
+        let (_, mut key) = root_key();
+        key.chain_code = vec![0, 1];
+        assert_eq!(derive_public_key(&key, &[vec![1]]), Err("Invalid chain code".to_string()));
+    }
+}
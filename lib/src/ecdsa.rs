@@ -28,7 +28,7 @@ pub mod reply {
 pub mod request {
     use super::*;
 
-    #[derive(CandidType, Serialize, Debug)]
+    #[derive(CandidType, Serialize, Debug, Deserialize)]
     pub struct ECDSAPublicKey {
         pub canister_id: Option<CanisterId>,
         pub derivation_path: Vec<Vec<u8>>,
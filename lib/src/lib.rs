@@ -1,6 +1,4 @@
 #[cfg(not(test))]
-use ic_cdk::api::time as ic_timestamp;
-#[cfg(not(test))]
 use ic_cdk::api::call::call_with_payment as ic_call;
 use ic_cdk::export::{
     candid::CandidType,
@@ -10,17 +8,31 @@ use ic_cdk::export::{
 #[cfg(test)]
 mod mocks;
 #[cfg(test)]
-use mocks::{ic_call, ic_timestamp};
+use mocks::ic_call;
+
+pub mod runtime;
+use runtime::{
+    msg_cycles_accept, msg_cycles_available, msg_cycles_refunded, stable64_grow, stable64_read, stable64_size,
+    stable64_write, time as ic_timestamp,
+};
 
 mod utils;
-pub use utils::u64_to_u256;
+pub use utils::{get_create2_address, get_create_address, u64_to_u256};
 use utils::{get_address_from_public_key, get_derivation_path};
 
 use primitive_types::U256;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 
 mod ecdsa;
-use ecdsa::reply::*;
-use ecdsa::request::*;
+
+pub mod derivation;
+
+pub mod signer;
+use signer::get_signer;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub mod state;
 use state::*;
@@ -28,25 +40,109 @@ use state::*;
 pub mod transaction;
 use transaction::*;
 
+pub mod types;
+use types::{Address, Bytes, H256};
+
+pub mod builder;
+use builder::TransactionBuilder;
+
+pub mod abi;
+
+pub mod verify;
+use abi::ConstructorCall;
+
+pub mod nonce;
+use nonce::NonceStatus;
+
+pub mod audit;
+
+mod migration;
+use audit::{AuditEntry, AuditLogPage, AuditOperation};
+
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "rpc")]
+use rpc::{RpcClient, RpcTransport};
+
+/// Most receipts fetched by one [`poll_receipts`] call.
+pub const MAX_RECEIPTS_PER_POLL: usize = 20;
+
+/// Deterministic deployment proxy available at the same address on most EVM
+/// chains. It deploys the calldata after the first 32 bytes with CREATE2,
+/// using those 32 bytes as the salt.
+pub const DETERMINISTIC_DEPLOYER: Address = Address([
+    0x4e, 0x59, 0xb4, 0x48, 0x47, 0xb3, 0x79, 0x57, 0x85, 0x88, 0x92, 0x0c, 0xa7, 0x8f, 0xbf, 0x26, 0xc0, 0xb4, 0x95, 0x6c,
+]);
+
 #[derive(CandidType, Serialize, Debug)]
 pub struct CreateAddressResponse {
-    pub address: String,
+    pub address: Address,
 }
 #[derive(CandidType, Deserialize, Debug)]
 pub struct SignTransactionResponse {
     pub sign_tx: Vec<u8>,
 }
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct SignTransactionRequest {
+    pub hex_raw_tx: Vec<u8>,
+    pub chain_id: u64,
+    pub assign_nonce: bool,
+}
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct SignOptions {
+    /// Signs legacy transactions without EIP-155 replay protection, so that
+    /// they are valid on every chain.
+    pub pre_eip155: bool,
+    /// Accepts an already signed transaction when it was signed with the
+    /// user's address, and returns it unchanged instead of signing it again.
+    pub verify_signed: bool,
+}
 #[derive(CandidType, Deserialize, Debug)]
 pub struct DeployContractResponse {
     pub tx: Vec<u8>,
+    pub contract_address: Address,
 }
 #[derive(CandidType, Deserialize, Debug)]
 pub struct TransferERC20Response {
     pub tx: Vec<u8>,
 }
 #[derive(CandidType, Deserialize, Debug)]
+pub struct ReplaceTransactionResponse {
+    pub tx: Vec<u8>,
+}
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum TransactionRef {
+    Hash(Vec<u8>),
+    Nonce(u64),
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionFees {
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: u64,
+    /// Sign a legacy transaction with `max_fee_per_gas` as gas price, for
+    /// chains without a base fee.
+    pub legacy: bool,
+}
+#[derive(CandidType, Deserialize, Debug)]
+pub struct BroadcastTransactionResponse {
+    pub hash: H256,
+}
+/// Signature of a raw hash. `v` is 27 or 28, as expected by `ecrecover`.
+#[derive(CandidType, Deserialize, Debug)]
+pub struct SignHashResponse {
+    pub r: H256,
+    pub s: H256,
+    pub v: u8,
+}
+#[derive(CandidType, Deserialize, Debug)]
+pub struct ValidateTransactionResponse {
+    pub transaction: TransactionSummary,
+    pub message_hash: H256,
+}
+#[derive(CandidType, Deserialize, Debug)]
 pub struct UserResponse {
-    pub address: String,
+    pub address: Address,
     pub transactions: TransactionChainData,
 }
 
@@ -55,6 +151,7 @@ pub fn init(env_opt: Option<Environment>) {
         STATE.with(|s| {
             let mut state = s.borrow_mut();
             state.config = Config::from(env);
+            state.root_key = None;
         })
     }
 }
@@ -67,39 +164,72 @@ pub async fn create_address(principal_id: Principal) -> Result<CreateAddressResp
         return Err("this wallet already exist".to_string());
     }
 
-    let key_id = EcdsaKeyId {
-        curve: EcdsaCurve::Secp256k1,
-        name: state.config.key_name
-    };
+    register_user(principal_id).await?;
+    let address = get_address(principal_id)?;
 
-    let caller = get_derivation_path(principal_id);
+    Ok(CreateAddressResponse { address })
+}
 
-    let request = ECDSAPublicKey {
-        canister_id: None,
-        derivation_path: vec![caller],
-        key_id: key_id.clone(),
-    };
+/// Returns the address of any principal, registered or not. Only the cached
+/// root key is needed, so this can be used from queries once the root key was
+/// fetched by an update call.
+pub fn get_address(principal_id: Principal) -> Result<Address, String> {
+    let public_key = STATE.with(|s| {
+        let state = s.borrow();
+        if let Some(user) = state.users.get(&principal_id) {
+            return Ok(user.public_key.clone());
+        }
 
-    let (res,): (ECDSAPublicKeyResponse,) = ic_call(
-        Principal::management_canister(),
-        "ecdsa_public_key",
-        (request,),
-        0 as u64
-    )
-    .await
-    .map_err(|e| format!("Failed to call ecdsa_public_key {}", e.1))?;
+        let root_key = state
+            .root_key
+            .as_ref()
+            .ok_or_else(|| "root key is not cached yet".to_string())?;
+        derive_user_public_key(root_key, principal_id)
+    })?;
 
-    let address = get_address_from_public_key(res.public_key.clone()).unwrap();
+    get_address_from_public_key(public_key)
+}
 
-    let mut user = UserData::default();
-    user.public_key = res.public_key;
+/// Same as [`get_address`], fetching the root key first if needed.
+pub async fn fetch_address(principal_id: Principal) -> Result<Address, String> {
+    get_root_key().await?;
+    get_address(principal_id)
+}
+
+fn derive_user_public_key(root_key: &ExtendedPublicKey, principal_id: Principal) -> Result<Vec<u8>, String> {
+    let caller = get_derivation_path(principal_id);
+    Ok(derivation::derive_public_key(root_key, &[caller])?.public_key)
+}
+
+// Stores the user's public key on first use, so that signing does not require
+// a prior `create_address` call.
+async fn register_user(principal_id: Principal) -> Result<(), String> {
+    if STATE.with(|s| s.borrow().users.contains_key(&principal_id)) {
+        return Ok(());
+    }
+
+    let root_key = get_root_key().await?;
+    let public_key = derive_user_public_key(&root_key, principal_id)?;
 
     STATE.with(|s| {
         let mut state = s.borrow_mut();
-        state.users.insert(principal_id, user);
+        state.users.entry(principal_id).or_insert_with(|| UserData {
+            public_key,
+            ..Default::default()
+        });
     });
+    Ok(())
+}
 
-    Ok(CreateAddressResponse { address })
+/// Returns the canister's root key, fetching it from the signer on first use.
+pub async fn get_root_key() -> Result<ExtendedPublicKey, String> {
+    if let Some(root_key) = STATE.with(|s| s.borrow().root_key.clone()) {
+        return Ok(root_key);
+    }
+
+    let root_key = get_signer().public_key(vec![]).await?;
+    STATE.with(|s| s.borrow_mut().root_key = Some(root_key.clone()));
+    Ok(root_key)
 }
 
 pub async fn sign_transaction(
@@ -107,113 +237,626 @@ pub async fn sign_transaction(
     chain_id: u64,
     principal_id: Principal,
 ) -> Result<SignTransactionResponse, String> {
+    sign_transaction_with_options(hex_raw_tx, chain_id, principal_id, SignOptions::default()).await
+}
+
+pub async fn sign_transaction_with_options(
+    hex_raw_tx: Vec<u8>,
+    chain_id: u64,
+    principal_id: Principal,
+    options: SignOptions,
+) -> Result<SignTransactionResponse, String> {
+    let result = sign_transaction_unaudited(hex_raw_tx.clone(), chain_id, principal_id, options).await;
+    let operation = match &result {
+        Ok(res) if res.sign_tx == hex_raw_tx => AuditOperation::VerifyTransaction,
+        _ => AuditOperation::SignTransaction,
+    };
+    audit_transaction(principal_id, chain_id, operation, result.as_ref().map(|r| r.sign_tx.as_slice()));
+    result
+}
+
+async fn sign_transaction_unaudited(
+    hex_raw_tx: Vec<u8>,
+    chain_id: u64,
+    principal_id: Principal,
+    options: SignOptions,
+) -> Result<SignTransactionResponse, String> {
+    let mut tx = decode_transaction(&hex_raw_tx, chain_id, &options)?;
+    if tx.is_signed() {
+        let address = fetch_address(principal_id).await?;
+        let signer = verify::recover_signer(tx.as_ref())?;
+        if signer != address {
+            return Err(format!("transaction is signed by {}, expected {}", signer, address));
+        }
+        return Ok(SignTransactionResponse { sign_tx: hex_raw_tx });
+    }
+    register_user(principal_id).await?;
+
+    let nonce = tx.get_nonce()?;
+    let claimed = with_chain_data(principal_id, chain_id, |chain_data| {
+        chain_data.nonce_manager.claim(nonce)
+    })?;
+
+    let signed_tx = match sign_with_user_key(tx.as_mut(), principal_id).await {
+        Ok(signed_tx) => signed_tx,
+        Err(e) => {
+            if claimed {
+                release_nonce(principal_id, chain_id, nonce)?;
+            }
+            return Err(e);
+        }
+    };
+
+    with_chain_data(principal_id, chain_id, |chain_data| {
+        record_transaction(chain_data, &signed_tx);
+        chain_data.nonce_manager.mark_signed(nonce);
+        Ok(())
+    })?;
+
+    Ok(SignTransactionResponse { sign_tx: signed_tx })
+}
+
+/// Decodes and checks a transaction the way `sign_transaction` would, and
+/// returns its fields with the hash that would be signed. Nothing is reserved
+/// and the management canister is not called, so this can run as a query.
+pub fn validate_transaction(
+    hex_raw_tx: Vec<u8>,
+    chain_id: u64,
+    principal_id: Principal,
+) -> Result<ValidateTransactionResponse, String> {
+    let tx = decode_transaction(&hex_raw_tx, chain_id, &SignOptions::default())?;
+    let summary = tx.get_summary();
+    check_fees(&hex_raw_tx, summary.gas_limit)?;
+
+    STATE.with(|s| {
+        let state = s.borrow();
+        let nonce_manager = state
+            .users
+            .get(&principal_id)
+            .and_then(|user| user.transactions.get(&chain_id))
+            .map(|chain_data| chain_data.nonce_manager.clone())
+            .unwrap_or_default();
+        nonce_manager.check(summary.nonce)
+    })?;
+
+    let message_hash = H256::try_from(&tx.get_message_to_sign()?[..])?;
+    Ok(ValidateTransactionResponse {
+        transaction: summary,
+        message_hash,
+    })
+}
+
+// The history and nonces are kept under `chain_id`, so the chain id in the
+// payload, if any, has to match it.
+fn decode_transaction(hex_raw_tx: &[u8], chain_id: u64, options: &SignOptions) -> Result<Box<dyn Sign>, String> {
+    let payload_chain_id = transaction::get_chain_id(hex_raw_tx)?;
+    if let Some(payload_chain_id) = payload_chain_id {
+        if payload_chain_id != chain_id {
+            return Err(format!("chain id {} does not match {}", payload_chain_id, chain_id));
+        }
+    }
+
+    let is_legacy = get_transaction_type(hex_raw_tx)? == TransactionType::Legacy;
+    if options.pre_eip155 && !is_legacy {
+        return Err("only legacy transactions can be signed without EIP-155".to_string());
+    }
+    if !options.pre_eip155 && is_legacy && chain_id == 0 {
+        return Err("chain id 0 requires pre-EIP-155 signing".to_string());
+    }
+
+    // a chain id in the payload takes precedence over the opt-in
+    let unprotected = is_legacy && payload_chain_id.is_none();
+    let tx = transaction::get_transaction(hex_raw_tx, if unprotected && options.pre_eip155 { 0 } else { chain_id })?;
+    if tx.is_signed() {
+        if !options.verify_signed {
+            return Err("transaction is already signed".to_string());
+        }
+        if unprotected && !options.pre_eip155 {
+            return Err("transaction is not EIP-155 protected".to_string());
+        }
+    }
+    Ok(tx)
+}
+
+fn check_fees(hex_raw_tx: &[u8], gas_limit: u64) -> Result<(), String> {
+    if gas_limit < MIN_GAS_LIMIT {
+        return Err(format!("gas limit {} is below {}", gas_limit, MIN_GAS_LIMIT));
+    }
+    if get_transaction_type(hex_raw_tx)? == TransactionType::EIP1559 {
+        let tx = Transaction1559::try_from(hex_raw_tx.to_vec())?;
+        if tx.max_priority_fee_per_gas > tx.max_fee_per_gas {
+            return Err("max priority fee per gas exceeds max fee per gas".to_string());
+        }
+    }
+    Ok(())
+}
+
+async fn sign_with_user_key(tx: &mut dyn Sign, principal_id: Principal) -> Result<Vec<u8>, String> {
     let state = STATE.with(|s| s.borrow().clone());
-    let user;
+    let user = state
+        .users
+        .get(&principal_id)
+        .ok_or_else(|| "this user does not exist".to_string())?;
 
-    if let Some(i) = state.users.get(&principal_id) {
-        user = i.clone();
-    } else {
-        return Err("this user does not exist".to_string());
+    let message = tx.get_message_to_sign()?;
+    if message.len() != 32 {
+        return Err("message to sign must be 32 bytes".to_string());
     }
 
-    let mut tx = transaction::get_transaction(&hex_raw_tx, chain_id.clone()).unwrap();
+    let public_key = user.public_key.clone();
+    let (signed_tx, cost) = sign_with_cycles(principal_id, message, |signature| tx.sign(signature, public_key)).await?;
+    SIGN_COSTS.with(|c| c.borrow_mut().insert(utils::get_transaction_hash(&signed_tx), cost));
 
-    let message = tx.get_message_to_sign().unwrap();
+    Ok(signed_tx)
+}
 
-    assert!(message.len() == 32);
+/// Signs a precomputed 32-byte hash with the user's key.
+///
+/// None of the transaction checks apply, so the canister must allow it with
+/// [`set_raw_signing`] and the user must opt in with [`set_user_raw_signing`].
+/// Every call is recorded in the audit log.
+pub async fn sign_hash(principal_id: Principal, message_hash: Vec<u8>) -> Result<SignHashResponse, String> {
+    let result = sign_hash_unaudited(principal_id, &message_hash).await;
+    let cycles = result.as_ref().map_or(0, |(_, cost)| *cost);
+    let result = result.map(|(response, _)| response);
+    audit(AuditEntry {
+        timestamp: ic_timestamp(),
+        caller: principal_id,
+        account: get_address(principal_id).ok(),
+        chain_id: None,
+        operation: AuditOperation::SignHash,
+        hash: Some(message_hash),
+        cycles,
+        outcome: result.as_ref().map(|_| ()).map_err(|e| e.clone()),
+    });
+    result
+}
 
-    let key_id = EcdsaKeyId {
-        curve: EcdsaCurve::Secp256k1,
-        name: state.config.key_name,
-    };
+// Also returns the cycles spent on `sign_with_ecdsa`.
+async fn sign_hash_unaudited(principal_id: Principal, message_hash: &[u8]) -> Result<(SignHashResponse, u64), String> {
+    if message_hash.len() != 32 {
+        return Err("message hash must be 32 bytes".to_string());
+    }
 
-    let caller = get_derivation_path(principal_id);
+    let public_key = STATE.with(|s| {
+        let state = s.borrow();
+        if !state.raw_signing_enabled {
+            return Err("raw hash signing is disabled".to_string());
+        }
+        match state.users.get(&principal_id) {
+            Some(user) if user.raw_signing_enabled => Ok(user.public_key.clone()),
+            _ => Err("raw hash signing is not enabled for this user".to_string()),
+        }
+    })?;
 
-    let request = SignWithECDSA {
-        message_hash: message.clone(),
-        derivation_path: vec![caller],
-        key_id: key_id.clone(),
-    };
+    sign_with_cycles(principal_id, message_hash.to_vec(), |signature| {
+        let signature = normalize_signature(&signature)?;
+        let recovery_id = transaction::get_recovery_id(message_hash, &signature, &public_key)?;
 
-    let (res,): (SignWithECDSAResponse,) = ic_call(
-        Principal::management_canister(),
-        "sign_with_ecdsa",
-        (request,),
-        state.config.sign_cycles
-    )
+        Ok(SignHashResponse {
+            r: H256::try_from(&signature[..32])?,
+            s: H256::try_from(&signature[32..])?,
+            v: 27 + recovery_id,
+        })
+    })
     .await
-    .map_err(|e| format!("Failed to call sign_with_ecdsa {}", e.1))?;
+}
+
+/// Controller switch for [`sign_hash`]. It is off by default.
+pub fn set_raw_signing(principal_id: Principal, enabled: bool) -> Result<(), String> {
+    let result = check_controller(principal_id);
+    if result.is_ok() {
+        STATE.with(|s| s.borrow_mut().raw_signing_enabled = enabled);
+    }
+    audit_admin(principal_id, AuditOperation::SetRawSigning { enabled }, result.clone());
+    result
+}
+
+/// Lets a user allow or forbid [`sign_hash`] on their own key.
+pub async fn set_user_raw_signing(principal_id: Principal, enabled: bool) -> Result<(), String> {
+    let result = async {
+        register_user(principal_id).await?;
+
+        STATE.with(|s| {
+            let mut state = s.borrow_mut();
+            let user = state
+                .users
+                .get_mut(&principal_id)
+                .ok_or_else(|| "this user does not exist".to_string())?;
+            user.raw_signing_enabled = enabled;
+            Ok(())
+        })
+    }
+    .await;
+
+    audit_admin(principal_id, AuditOperation::SetUserRawSigning { enabled }, result.clone());
+    result
+}
+
+thread_local! {
+    // Cycles spent on `sign_with_ecdsa` per signed transaction hash, kept
+    // until the signing call is written to the audit log.
+    static SIGN_COSTS: RefCell<HashMap<Vec<u8>, u64>> = RefCell::new(HashMap::new());
+}
+
+fn take_sign_cost(hash: &Vec<u8>) -> u64 {
+    SIGN_COSTS.with(|c| c.borrow_mut().remove(hash)).unwrap_or(0)
+}
+
+/// Signs with the user's key and pays the signature from their cycles balance.
+///
+/// The cycles attached to `sign_with_ecdsa` are reserved before the call, so
+/// concurrent signatures cannot overdraw the balance, and what the call did not
+/// spend is credited back. The signature is passed to `finish`, and when either
+/// fails the whole reservation is credited back, so users only pay for
+/// signatures they get. The cycles spent are returned with the result.
+async fn sign_with_cycles<T>(
+    principal_id: Principal,
+    message_hash: Vec<u8>,
+    finish: impl FnOnce(Vec<u8>) -> Result<T, String>,
+) -> Result<(T, u64), String> {
+    let reserved = sign_cycles();
+    with_user(principal_id, |user| {
+        if user.cycles_balance < reserved {
+            return Err(format!(
+                "insufficient cycles balance: {} available, {} required",
+                user.cycles_balance, reserved
+            ));
+        }
+        user.cycles_balance -= reserved;
+        Ok(())
+    })?;
+
+    let caller = get_derivation_path(principal_id);
+    let result = get_signer()
+        .sign_digest_with_cost(vec![caller], message_hash)
+        .await
+        .and_then(|(signature, cost)| Ok((finish(signature)?, cost.min(reserved))));
+
+    let cost = result.as_ref().map_or(0, |(_, cost)| *cost);
+    with_user(principal_id, |user| {
+        user.cycles_balance += reserved - cost;
+        Ok(())
+    })?;
+    result
+}
+
+/// Credits the cycles attached to the call to the user's balance and returns
+/// the new balance.
+pub async fn deposit_cycles(principal_id: Principal) -> Result<u64, String> {
+    // Cycles are only accepted once they are on the balance, so a failed credit
+    // refunds them to the caller.
+    let amount = msg_cycles_available();
+    let balance = credit_cycles(principal_id, amount).await?;
+    msg_cycles_accept(amount);
+    Ok(balance)
+}
+
+/// Adds `amount` cycles to the user's balance and returns the new balance.
+///
+/// Canisters that take payments through the ICP or cycles ledger call this
+/// once the transfer is confirmed. It must not be exposed to users directly.
+pub async fn credit_cycles(principal_id: Principal, amount: u64) -> Result<u64, String> {
+    let result = async {
+        register_user(principal_id).await?;
+
+        with_user(principal_id, |user| {
+            user.cycles_balance = user
+                .cycles_balance
+                .checked_add(amount)
+                .ok_or_else(|| "cycles balance overflow".to_string())?;
+            Ok(user.cycles_balance)
+        })
+    }
+    .await;
+
+    audit(AuditEntry {
+        account: get_address(principal_id).ok(),
+        ..admin_entry(
+            principal_id,
+            AuditOperation::DepositCycles { amount },
+            result.as_ref().map(|_| ()).map_err(|e| e.clone()),
+        )
+    });
+    result
+}
 
-    let signed_tx = tx.sign(res.signature.clone(), user.public_key).unwrap();
+/// Returns the user's cycles balance, 0 when the user does not exist.
+pub fn get_cycles_balance(principal_id: Principal) -> u64 {
+    STATE.with(|s| s.borrow().users.get(&principal_id).map_or(0, |user| user.cycles_balance))
+}
 
+fn with_user<T>(principal_id: Principal, f: impl FnOnce(&mut UserData) -> Result<T, String>) -> Result<T, String> {
     STATE.with(|s| {
         let mut state = s.borrow_mut();
-        let user = state.users.get_mut(&principal_id).unwrap();
+        let user = state
+            .users
+            .get_mut(&principal_id)
+            .ok_or_else(|| "this user does not exist".to_string())?;
+        f(user)
+    })
+}
 
-        let mut transaction = Transaction::default();
-        transaction.data = signed_tx.clone();
-        transaction.timestamp = ic_timestamp();
+/// Records a signing call. `result` holds the signed transaction on success.
+fn audit_transaction(principal_id: Principal, chain_id: u64, operation: AuditOperation, result: Result<&[u8], &String>) {
+    audit(transaction_entry(principal_id, chain_id, operation, result));
+}
 
-        if let Some(user_tx) = user.transactions.get_mut(&chain_id) {
-            user_tx.transactions.push(transaction);
-            user_tx.nonce = tx.get_nonce().unwrap() + 1;
-        } else {
-            let mut chain_data = TransactionChainData::default();
-            chain_data.nonce = tx.get_nonce().unwrap() + 1;
-            chain_data.transactions.push(transaction);
+fn transaction_entry(
+    principal_id: Principal,
+    chain_id: u64,
+    operation: AuditOperation,
+    result: Result<&[u8], &String>,
+) -> AuditEntry {
+    let hash = result.ok().map(utils::get_transaction_hash);
+    // verification returns the input without calling `sign_with_ecdsa`, so no cost is recorded for it
+    let cycles = hash.as_ref().map_or(0, take_sign_cost);
+    AuditEntry {
+        timestamp: ic_timestamp(),
+        caller: principal_id,
+        account: get_address(principal_id).ok(),
+        chain_id: Some(chain_id),
+        operation,
+        hash,
+        cycles,
+        outcome: result.map(|_| ()).map_err(|e| e.clone()),
+    }
+}
 
-            user.transactions.insert(chain_id, chain_data);
-        }
+fn audit_admin(principal_id: Principal, operation: AuditOperation, outcome: Result<(), String>) {
+    audit(admin_entry(principal_id, operation, outcome));
+}
+
+fn admin_entry(principal_id: Principal, operation: AuditOperation, outcome: Result<(), String>) -> AuditEntry {
+    AuditEntry {
+        timestamp: ic_timestamp(),
+        caller: principal_id,
+        account: None,
+        chain_id: None,
+        operation,
+        hash: None,
+        cycles: 0,
+        outcome,
+    }
+}
+
+fn audit_nonce<T>(principal_id: Principal, chain_id: u64, operation: AuditOperation, result: &Result<T, String>) {
+    let outcome = result.as_ref().map(|_| ()).map_err(Clone::clone);
+    audit(AuditEntry {
+        chain_id: Some(chain_id),
+        ..admin_entry(principal_id, operation, outcome)
     });
+}
 
-    Ok(SignTransactionResponse { sign_tx: signed_tx })
+/// Appends `entry` on a best-effort basis. Most entries are written after a
+/// signature was handed out, so trapping when stable memory can't grow would
+/// roll back its bookkeeping (cycles, nonce, history) but not the signature.
+/// The entry is dropped instead and counted in [`AuditLogPage::dropped`].
+fn audit(entry: AuditEntry) {
+    if audit::append(&entry).is_err() {
+        audit::record_dropped();
+    }
+}
+
+fn sign_cycles() -> u64 {
+    STATE.with(|s| s.borrow().config.sign_cycles)
+}
+
+fn record_transaction(chain_data: &mut TransactionChainData, signed_tx: &[u8]) {
+    let transaction = Transaction {
+        data: signed_tx.to_vec(),
+        hash: utils::get_transaction_hash(signed_tx),
+        timestamp: ic_timestamp(),
+        ..Default::default()
+    };
+
+    chain_data.transactions.push(transaction);
+}
+
+/// Signs several transactions, possibly for different chains, in one call.
+///
+/// Nonces are claimed in request order, so transactions for the same chain must
+/// carry consecutive nonces unless `assign_nonce` is set. The `sign_with_ecdsa`
+/// calls run concurrently and each request gets its own result. When signing
+/// fails after a nonce was claimed, the later transactions for the same chain
+/// fail too and give back their nonces, so no gap is left behind.
+pub async fn sign_transactions_batch(
+    requests: Vec<SignTransactionRequest>,
+    principal_id: Principal,
+) -> Vec<Result<SignTransactionResponse, String>> {
+    if let Err(e) = register_user(principal_id).await {
+        for request in &requests {
+            audit_transaction(principal_id, request.chain_id, AuditOperation::SignTransaction, Err(&e));
+        }
+        return requests.iter().map(|_| Err(e.clone())).collect();
+    }
+
+    let mut prepared = requests
+        .iter()
+        .map(|request| prepare_batch_transaction(request, principal_id))
+        .collect::<Vec<_>>();
+
+    let signatures = futures::future::join_all(prepared.iter_mut().map(|item| async move {
+        match item {
+            Ok((tx, _, _)) => sign_with_user_key(tx.as_mut(), principal_id).await,
+            Err(e) => Err(e.clone()),
+        }
+    }))
+    .await;
+
+    let mut failed_chains = HashSet::new();
+    requests
+        .iter()
+        .zip(prepared)
+        .zip(signatures)
+        .map(|((request, item), signature)| {
+            // a signature discarded after an earlier failure on its chain was still paid for
+            let discarded_cost = match &signature {
+                Ok(signed_tx) if failed_chains.contains(&request.chain_id) => {
+                    take_sign_cost(&utils::get_transaction_hash(signed_tx))
+                }
+                _ => 0,
+            };
+            let result = finish_batch_transaction(request, item, signature, principal_id, &mut failed_chains);
+            let operation = AuditOperation::SignTransaction;
+            let entry =
+                transaction_entry(principal_id, request.chain_id, operation, result.as_ref().map(|r| r.sign_tx.as_slice()));
+            audit(AuditEntry {
+                cycles: entry.cycles + discarded_cost,
+                ..entry
+            });
+            result
+        })
+        .collect()
+}
+
+fn finish_batch_transaction(
+    request: &SignTransactionRequest,
+    item: Result<(Box<dyn Sign>, u64, bool), String>,
+    signature: Result<Vec<u8>, String>,
+    principal_id: Principal,
+    failed_chains: &mut HashSet<u64>,
+) -> Result<SignTransactionResponse, String> {
+    let (_, nonce, claimed) = item?;
+    let signature = if failed_chains.contains(&request.chain_id) {
+        Err(format!("an earlier transaction for chain {} failed", request.chain_id))
+    } else {
+        signature
+    };
+
+    match signature {
+        Ok(signed_tx) => {
+            with_chain_data(principal_id, request.chain_id, |chain_data| {
+                record_transaction(chain_data, &signed_tx);
+                chain_data.nonce_manager.mark_signed(nonce);
+                Ok(())
+            })?;
+            Ok(SignTransactionResponse { sign_tx: signed_tx })
+        }
+        Err(e) => {
+            failed_chains.insert(request.chain_id);
+            if claimed {
+                release_nonce(principal_id, request.chain_id, nonce)?;
+            }
+            Err(e)
+        }
+    }
+}
+
+fn prepare_batch_transaction(
+    request: &SignTransactionRequest,
+    principal_id: Principal,
+) -> Result<(Box<dyn Sign>, u64, bool), String> {
+    let mut tx = decode_transaction(&request.hex_raw_tx, request.chain_id, &SignOptions::default())?;
+
+    with_chain_data(principal_id, request.chain_id, |chain_data| {
+        if request.assign_nonce {
+            let nonce = chain_data.nonce_manager.reserve();
+            tx.set_nonce(nonce);
+            Ok((nonce, true))
+        } else {
+            let nonce = tx.get_nonce()?;
+            let claimed = chain_data.nonce_manager.claim(nonce)?;
+            Ok((nonce, claimed))
+        }
+    })
+    .map(|(nonce, claimed)| (tx, nonce, claimed))
+}
+
+/// Appends the ABI-encoded constructor arguments, if any, to `bytecode`.
+pub fn get_init_code(bytecode: Vec<u8>, constructor: Option<ConstructorCall>) -> Result<Vec<u8>, String> {
+    if bytecode.is_empty() {
+        return Err("Bytecode is empty".to_string());
+    }
+
+    let mut init_code = bytecode;
+    if let Some(constructor) = constructor {
+        init_code.extend(abi::encode_args(&constructor.signature, &constructor.args)?);
+    }
+    Ok(init_code)
 }
 
 pub async fn deploy_contract(
     principal_id: Principal,
     bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
     chain_id: u64,
     max_priority_fee_per_gas: U256,
     gas_limit: u64,
     max_fee_per_gas: U256,
 ) -> Result<DeployContractResponse, String> {
-    let users = STATE.with(|s| s.borrow().users.clone());
-    let user;
+    let result = async {
+        let init_code = get_init_code(bytecode, constructor)?;
+        let fees = TransactionFees {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            legacy: false,
+        };
+        let res = sign_transaction_with_fees(principal_id, chain_id, None, init_code, fees).await?;
 
-    if let Some(i) = users.get(&principal_id) {
-        user = i.clone();
-    } else {
-        return Err("this user does not exist".to_string());
+        create_deploy_response(principal_id, chain_id, res.sign_tx)
     }
+    .await;
 
-    let nonce: u64;
-    if let Some(user_transactions) = user.transactions.get(&chain_id) {
-        nonce = user_transactions.nonce;
-    } else {
-        nonce = 0;
-    }
-    let data = "0x".to_owned() + &utils::vec_u8_to_string(&bytecode);
-    let tx = transaction::Transaction1559 {
-        nonce,
-        chain_id,
-        max_priority_fee_per_gas,
-        gas_limit,
-        max_fee_per_gas,
-        to: "0x".to_string(),
-        value: U256::zero(),
-        data,
-        access_list: vec![],
-        v: "0x00".to_string(),
-        r: "0x00".to_string(),
-        s: "0x00".to_string(),
-    };
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::DeployContract, signed_tx);
+    result
+}
 
-    let raw_tx = tx.serialize().unwrap();
-    let res = sign_transaction(raw_tx, chain_id, principal_id)
-        .await
-        .unwrap();
+/// Deploys `bytecode` through the deterministic deployment proxy, so the
+/// contract address only depends on `salt` and the init code.
+#[allow(clippy::too_many_arguments)]
+pub async fn deploy_contract_create2(
+    principal_id: Principal,
+    bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
+    salt: Vec<u8>,
+    chain_id: u64,
+    max_priority_fee_per_gas: U256,
+    gas_limit: u64,
+    max_fee_per_gas: U256,
+) -> Result<DeployContractResponse, String> {
+    let result = async {
+        let init_code = get_init_code(bytecode, constructor)?;
+        let init_code_hash = easy_hasher::easy_hasher::raw_keccak256(init_code.clone()).to_vec();
+        let contract_address = get_create2_address(&DETERMINISTIC_DEPLOYER, &salt, &init_code_hash)?;
+
+        let mut data = salt;
+        data.extend(init_code);
+        let fees = TransactionFees {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            legacy: false,
+        };
+        let to = Some(DETERMINISTIC_DEPLOYER);
+        let res = sign_transaction_with_fees(principal_id, chain_id, to, data, fees).await?;
+
+        Ok(DeployContractResponse {
+            tx: res.sign_tx,
+            contract_address,
+        })
+    }
+    .await;
 
-    Ok(DeployContractResponse { tx: res.sign_tx })
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::DeployContract, signed_tx);
+    result
+}
+
+fn create_deploy_response(
+    principal_id: Principal,
+    chain_id: u64,
+    signed_tx: Vec<u8>,
+) -> Result<DeployContractResponse, String> {
+    let nonce = transaction::get_transaction(&signed_tx, chain_id)?.get_nonce()?;
+    let sender = get_address(principal_id)?;
+
+    Ok(DeployContractResponse {
+        tx: signed_tx,
+        contract_address: get_create_address(&sender, nonce),
+    })
 }
 
 pub async fn transfer_erc_20(
@@ -222,66 +865,603 @@ pub async fn transfer_erc_20(
     max_priority_fee_per_gas: U256,
     gas_limit: u64,
     max_fee_per_gas: U256,
-    address: String,
+    address: Address,
     value: U256,
-    contract_address: String,
+    contract_address: Address,
 ) -> Result<TransferERC20Response, String> {
-    let users = STATE.with(|s| s.borrow().users.clone());
-    let user;
+    let result = async {
+        let data = utils::get_transfer_data(&address, value);
+        let fees = TransactionFees {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            legacy: false,
+        };
+        let res = sign_transaction_with_fees(principal_id, chain_id, Some(contract_address), data, fees).await?;
 
-    if let Some(i) = users.get(&principal_id) {
-        user = i.clone();
-    } else {
-        return Err("this user does not exist".to_string());
+        Ok(TransferERC20Response { tx: res.sign_tx })
+    }
+    .await;
+
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::TransferErc20, signed_tx);
+    result
+}
+
+/// Same as [`deploy_contract`] with fees and gas limit estimated over RPC.
+#[cfg(feature = "rpc")]
+pub async fn deploy_contract_with_estimated_fees(
+    principal_id: Principal,
+    bytecode: Vec<u8>,
+    constructor: Option<ConstructorCall>,
+    chain_id: u64,
+) -> Result<DeployContractResponse, String> {
+    let result = async {
+        let init_code = get_init_code(bytecode, constructor)?;
+        let transport = rpc::get_transport();
+        let fees = estimate_transaction_fees(principal_id, chain_id, None, &init_code, U256::zero(), transport.as_ref()).await?;
+        let res = sign_transaction_with_fees(principal_id, chain_id, None, init_code, fees).await?;
+
+        create_deploy_response(principal_id, chain_id, res.sign_tx)
+    }
+    .await;
+
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::DeployContract, signed_tx);
+    result
+}
+
+/// Same as [`transfer_erc_20`] with fees and gas limit estimated over RPC.
+#[cfg(feature = "rpc")]
+pub async fn transfer_erc_20_with_estimated_fees(
+    principal_id: Principal,
+    chain_id: u64,
+    address: Address,
+    value: U256,
+    contract_address: Address,
+) -> Result<TransferERC20Response, String> {
+    let result = async {
+        let data = utils::get_transfer_data(&address, value);
+        let transport = rpc::get_transport();
+        let fees = estimate_transaction_fees(
+            principal_id,
+            chain_id,
+            Some(contract_address),
+            &data,
+            U256::zero(),
+            transport.as_ref(),
+        )
+        .await?;
+        let res = sign_transaction_with_fees(principal_id, chain_id, Some(contract_address), data, fees).await?;
+
+        Ok(TransferERC20Response { tx: res.sign_tx })
     }
+    .await;
 
-    let nonce: u64;
-    if let Some(user_transactions) = user.transactions.get(&chain_id) {
-        nonce = user_transactions.nonce;
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::TransferErc20, signed_tx);
+    result
+}
+
+async fn sign_transaction_with_fees(
+    principal_id: Principal,
+    chain_id: u64,
+    to: Option<Address>,
+    data: Vec<u8>,
+    fees: TransactionFees,
+) -> Result<SignTransactionResponse, String> {
+    register_user(principal_id).await?;
+    #[cfg(feature = "rpc")]
+    sync_nonce_if_enabled(principal_id, chain_id).await?;
+
+    // audited as part of the signing call
+    let nonce = with_chain_data(principal_id, chain_id, |chain_data| {
+        Ok(chain_data.nonce_manager.reserve())
+    })?;
+
+    let mut builder = TransactionBuilder::new(chain_id)
+        .nonce(nonce)
+        .data(Bytes(data))
+        .gas_limit(fees.gas_limit);
+    builder = if fees.legacy {
+        builder.gas_price(fees.max_fee_per_gas)
     } else {
-        nonce = 0;
-    }
-
-    let data = "0x".to_owned() + &utils::get_transfer_data(&address, value).unwrap();
-
-    let tx = transaction::Transaction1559 {
-        nonce,
-        chain_id,
-        max_priority_fee_per_gas,
-        gas_limit,
-        max_fee_per_gas,
-        to: contract_address,
-        value: U256::zero(),
-        data,
-        access_list: vec![],
-        v: "0x00".to_string(),
-        r: "0x00".to_string(),
-        s: "0x00".to_string(),
+        builder
+            .max_fee_per_gas(fees.max_fee_per_gas)
+            .max_priority_fee_per_gas(fees.max_priority_fee_per_gas)
     };
+    if let Some(to) = to {
+        builder = builder.to(to);
+    }
 
-    let raw_tx = tx.serialize().unwrap();
+    let raw_tx = builder.build_raw()?;
+    sign_reserved_transaction(raw_tx, chain_id, principal_id, nonce).await
+}
 
-    let res = sign_transaction(raw_tx, chain_id, principal_id)
-        .await
-        .unwrap();
+pub async fn replace_transaction(
+    principal_id: Principal,
+    chain_id: u64,
+    tx_ref: TransactionRef,
+    fee_bump_percent: Option<u64>,
+) -> Result<ReplaceTransactionResponse, String> {
+    let result = async {
+        let mut tx = get_replaceable_transaction(principal_id, chain_id, &tx_ref)?;
+        tx.bump_fees(fee_bump_percent.unwrap_or(MIN_FEE_BUMP_PERCENT))?;
+
+        let signed_tx = sign_with_user_key(tx.as_mut(), principal_id).await?;
+
+        with_chain_data(principal_id, chain_id, |chain_data| {
+            record_transaction(chain_data, &signed_tx);
+            Ok(())
+        })?;
 
-    Ok(TransferERC20Response { tx: res.sign_tx })
+        Ok(ReplaceTransactionResponse { tx: signed_tx })
+    }
+    .await;
+
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::ReplaceTransaction, signed_tx);
+    result
 }
 
-pub fn get_caller_data(principal_id: Principal, chain_id: u64) -> Option<UserResponse> {
-    let users = STATE.with(|s| s.borrow().users.clone());
-    let user;
-    if let Some(i) = users.get(&principal_id) {
-        user = i.clone();
-    } else {
-        return None;
+pub async fn cancel_transaction(
+    principal_id: Principal,
+    chain_id: u64,
+    tx_ref: TransactionRef,
+    fee_bump_percent: Option<u64>,
+) -> Result<ReplaceTransactionResponse, String> {
+    let result = async {
+        let mut tx = get_replaceable_transaction(principal_id, chain_id, &tx_ref)?;
+        tx.bump_fees(fee_bump_percent.unwrap_or(MIN_FEE_BUMP_PERCENT))?;
+
+        let public_key = STATE.with(|s| s.borrow().users[&principal_id].public_key.clone());
+        let address = get_address_from_public_key(public_key)?;
+        tx.make_cancellation(&address);
+
+        let signed_tx = sign_with_user_key(tx.as_mut(), principal_id).await?;
+
+        with_chain_data(principal_id, chain_id, |chain_data| {
+            record_transaction(chain_data, &signed_tx);
+            Ok(())
+        })?;
+
+        Ok(ReplaceTransactionResponse { tx: signed_tx })
     }
+    .await;
 
-    let address = get_address_from_public_key(user.public_key.clone()).unwrap();
+    let signed_tx = result.as_ref().map(|r| r.tx.as_slice());
+    audit_transaction(principal_id, chain_id, AuditOperation::CancelTransaction, signed_tx);
+    result
+}
 
-    let transaction_data = user
+fn get_replaceable_transaction(
+    principal_id: Principal,
+    chain_id: u64,
+    tx_ref: &TransactionRef,
+) -> Result<Box<dyn Sign>, String> {
+    let users = STATE.with(|s| s.borrow().users.clone());
+    let user = users
+        .get(&principal_id)
+        .ok_or_else(|| "this user does not exist".to_string())?;
+    let chain_data = user
         .transactions
         .get(&chain_id)
+        .ok_or_else(|| "transaction not found".to_string())?;
+
+    let stored = chain_data
+        .transactions
+        .iter()
+        .rev()
+        .find(|stored| match tx_ref {
+            TransactionRef::Hash(hash) => &stored.hash == hash,
+            TransactionRef::Nonce(nonce) => transaction::get_transaction(&stored.data, chain_id)
+                .and_then(|tx| tx.get_nonce())
+                .is_ok_and(|n| n == *nonce),
+        })
+        .ok_or_else(|| "transaction not found".to_string())?;
+
+    let mut tx = transaction::get_transaction(&stored.data, chain_id)?;
+    let nonce = tx.get_nonce()?;
+    match chain_data.nonce_manager.status(nonce) {
+        Some(NonceStatus::Signed) | Some(NonceStatus::Broadcast) => {}
+        _ => return Err(format!("nonce {} can no longer be replaced", nonce)),
+    }
+
+    tx.clear_signature();
+    Ok(tx)
+}
+
+async fn sign_reserved_transaction(
+    raw_tx: Vec<u8>,
+    chain_id: u64,
+    principal_id: Principal,
+    nonce: u64,
+) -> Result<SignTransactionResponse, String> {
+    let result = sign_transaction_unaudited(raw_tx, chain_id, principal_id, SignOptions::default()).await;
+    if result.is_err() {
+        release_nonce(principal_id, chain_id, nonce)?;
+    }
+    result
+}
+
+pub fn reserve_nonce(principal_id: Principal, chain_id: u64) -> Result<u64, String> {
+    let result = with_chain_data(principal_id, chain_id, |chain_data| {
+        Ok(chain_data.nonce_manager.reserve())
+    });
+    let nonce = result.as_ref().ok().copied();
+    audit_nonce(principal_id, chain_id, AuditOperation::ReserveNonce { nonce }, &result);
+    result
+}
+
+pub fn release_nonce(principal_id: Principal, chain_id: u64, nonce: u64) -> Result<(), String> {
+    let result = with_chain_data(principal_id, chain_id, |chain_data| {
+        chain_data.nonce_manager.release(nonce)
+    });
+    audit_nonce(principal_id, chain_id, AuditOperation::ReleaseNonce { nonce }, &result);
+    result
+}
+
+pub fn mark_nonce_broadcast(principal_id: Principal, chain_id: u64, nonce: u64) -> Result<(), String> {
+    let result = with_chain_data(principal_id, chain_id, |chain_data| {
+        chain_data.nonce_manager.mark_broadcast(nonce)
+    });
+    audit_nonce(principal_id, chain_id, AuditOperation::MarkNonceBroadcast { nonce }, &result);
+    result
+}
+
+pub fn confirm_nonce(principal_id: Principal, chain_id: u64, nonce: u64) -> Result<(), String> {
+    let result = with_chain_data(principal_id, chain_id, |chain_data| {
+        chain_data.nonce_manager.confirm(nonce);
+        Ok(())
+    });
+    audit_nonce(principal_id, chain_id, AuditOperation::ConfirmNonce { nonce }, &result);
+    result
+}
+
+pub fn resync_nonce(
+    principal_id: Principal,
+    chain_id: u64,
+    transaction_count: u64,
+) -> Result<(), String> {
+    let result = with_chain_data(principal_id, chain_id, |chain_data| {
+        chain_data.nonce_manager.resync(transaction_count);
+        Ok(())
+    });
+    audit_nonce(principal_id, chain_id, AuditOperation::ResyncNonce { transaction_count }, &result);
+    result
+}
+
+fn with_chain_data<T>(
+    principal_id: Principal,
+    chain_id: u64,
+    f: impl FnOnce(&mut TransactionChainData) -> Result<T, String>,
+) -> Result<T, String> {
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let user = state
+            .users
+            .get_mut(&principal_id)
+            .ok_or_else(|| "this user does not exist".to_string())?;
+        let chain_data = user.transactions.entry(chain_id).or_default();
+
+        let result = f(chain_data);
+        chain_data.nonce = chain_data.nonce_manager.next_nonce();
+        result
+    })
+}
+
+/// Registers `controller` without a check, to bootstrap the list. Only call
+/// it from `init` or `post_upgrade`, where the caller controls the canister.
+pub fn init_controller(controller: Principal) {
+    if is_controller(controller) {
+        return;
+    }
+    STATE.with(|s| s.borrow_mut().controllers.push(controller));
+    audit_admin(controller, AuditOperation::AddController { principal: controller }, Ok(()));
+}
+
+/// Adds `controller` on behalf of `principal_id`, who must be a controller.
+pub fn add_controller(principal_id: Principal, controller: Principal) -> Result<(), String> {
+    let result = check_controller(principal_id);
+    if result.is_ok() {
+        STATE.with(|s| {
+            let mut state = s.borrow_mut();
+            if !state.controllers.contains(&controller) {
+                state.controllers.push(controller);
+            }
+        });
+    }
+    audit_admin(principal_id, AuditOperation::AddController { principal: controller }, result.clone());
+    result
+}
+
+pub fn is_controller(principal_id: Principal) -> bool {
+    STATE.with(|s| s.borrow().controllers.contains(&principal_id))
+}
+
+pub fn set_chain_config(principal_id: Principal, chain_id: u64, chain_config: ChainConfig) -> Result<(), String> {
+    let result = check_controller(principal_id)
+        .and_then(|_| utils::check_rpc_providers(&chain_config.rpc_providers, chain_config.rpc_threshold));
+    if result.is_ok() {
+        STATE.with(|s| {
+            let mut state = s.borrow_mut();
+            state.chains.insert(chain_id, chain_config);
+        });
+    }
+
+    audit(AuditEntry {
+        chain_id: Some(chain_id),
+        ..admin_entry(principal_id, AuditOperation::SetChainConfig, result.clone())
+    });
+    result
+}
+
+pub fn get_chain_config(chain_id: u64) -> Result<ChainConfig, String> {
+    STATE.with(|s| s.borrow().chains.get(&chain_id).cloned())
+        .ok_or_else(|| format!("chain {} is not registered", chain_id))
+}
+
+/// Reconciles the stored nonce with the pending transaction count reported by
+/// the chain and returns the next nonce to use.
+#[cfg(feature = "rpc")]
+pub async fn sync_nonce(
+    principal_id: Principal,
+    chain_id: u64,
+    transport: &dyn RpcTransport,
+) -> Result<u64, String> {
+    let result = async {
+        register_user(principal_id).await?;
+        let address = get_address(principal_id)?;
+
+        let chain_config = get_chain_config(chain_id)?;
+        let client = RpcClient::for_chain(transport, &chain_config)?;
+        let latest = client.get_transaction_count(&address, "latest").await?;
+        let pending = client.get_transaction_count(&address, "pending").await?;
+
+        with_chain_data(principal_id, chain_id, |chain_data| {
+            chain_data.nonce_manager.sync(latest, pending);
+            Ok(chain_data.nonce_manager.next_nonce())
+        })
+    }
+    .await;
+
+    audit_nonce(principal_id, chain_id, AuditOperation::SyncNonce, &result);
+    result
+}
+
+/// Returns the native token balance of the principal's address in wei.
+#[cfg(feature = "rpc")]
+pub async fn get_balance(
+    principal_id: Principal,
+    chain_id: u64,
+    transport: &dyn RpcTransport,
+) -> Result<U256, String> {
+    let address = fetch_address(principal_id).await?;
+
+    let chain_config = get_chain_config(chain_id)?;
+    let client = RpcClient::for_chain(transport, &chain_config)?;
+    client.get_balance(&address, "latest").await
+}
+
+/// Returns the principal's balance of an ERC-20 token in the token's smallest unit.
+#[cfg(feature = "rpc")]
+pub async fn get_erc_20_balance(
+    principal_id: Principal,
+    chain_id: u64,
+    contract_address: Address,
+    transport: &dyn RpcTransport,
+) -> Result<U256, String> {
+    let address = fetch_address(principal_id).await?;
+
+    let chain_config = get_chain_config(chain_id)?;
+    let client = RpcClient::for_chain(transport, &chain_config)?;
+    client.erc_20_balance_of(&contract_address, &address).await
+}
+
+/// Estimates EIP-1559 fees and the gas limit of a transaction sent from the
+/// principal's address, using the chain's `fee_estimation` settings.
+#[cfg(feature = "rpc")]
+pub async fn estimate_transaction_fees(
+    principal_id: Principal,
+    chain_id: u64,
+    to: Option<Address>,
+    data: &[u8],
+    value: U256,
+    transport: &dyn RpcTransport,
+) -> Result<TransactionFees, String> {
+    let from = fetch_address(principal_id).await?;
+
+    let chain_config = get_chain_config(chain_id)?;
+    let client = RpcClient::for_chain(transport, &chain_config)?;
+    let call = rpc::transaction_call(&from, to.as_ref(), data, value);
+
+    client.estimate_fees(&chain_config.fee_estimation, call).await
+}
+
+#[cfg(feature = "rpc")]
+async fn sync_nonce_if_enabled(principal_id: Principal, chain_id: u64) -> Result<(), String> {
+    if let Ok(chain_config) = get_chain_config(chain_id) {
+        if chain_config.auto_sync_nonce {
+            sync_nonce(principal_id, chain_id, rpc::get_transport().as_ref()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetches receipts of pending history entries on registered chains and
+/// records their outcome. Meant to be called periodically, e.g. from a heartbeat.
+/// Entries whose receipt cannot be fetched are retried on the next poll. Only
+/// entries with a broadcast nonce are polled, at most [`MAX_RECEIPTS_PER_POLL`]
+/// per call, oldest first.
+/// Returns the number of entries that left the pending state.
+#[cfg(feature = "rpc")]
+pub async fn poll_receipts(transport: &dyn RpcTransport) -> u64 {
+    let state = STATE.with(|s| s.borrow().clone());
+
+    // Only broadcast transactions can get a receipt, signed ones may never be sent.
+    let mut pending = vec![];
+    for (principal_id, user) in state.users.iter() {
+        for (chain_id, chain_data) in user.transactions.iter() {
+            let chain_config = match state.chains.get(chain_id) {
+                Some(chain_config) => chain_config,
+                None => continue,
+            };
+            for stored in chain_data.transactions.iter() {
+                let broadcast = transaction::get_transaction(&stored.data, *chain_id)
+                    .and_then(|tx| tx.get_nonce())
+                    .is_ok_and(|nonce| chain_data.nonce_manager.status(nonce) == Some(NonceStatus::Broadcast));
+                if stored.status == TransactionStatus::Pending && broadcast {
+                    pending.push((*principal_id, *chain_id, chain_config, stored));
+                }
+            }
+        }
+    }
+    pending.sort_by_key(|(_, _, _, stored)| stored.timestamp);
+    pending.truncate(MAX_RECEIPTS_PER_POLL);
+
+    let receipts = futures::future::join_all(pending.iter().map(|(_, _, chain_config, stored)| async move {
+        let client = RpcClient::for_chain(transport, chain_config)?;
+        let hash = H256::try_from(&stored.hash[..])?;
+        client.get_transaction_receipt(&hash).await
+    }))
+    .await;
+
+    let mut updated = 0;
+    for ((principal_id, chain_id, chain_config, stored), receipt) in pending.into_iter().zip(receipts) {
+        let timeout = chain_config.receipt_timeout_ns;
+        let expired = timeout > 0 && ic_timestamp().saturating_sub(stored.timestamp) > timeout;
+        let receipt = match receipt {
+            Ok(receipt) => receipt,
+            // entries whose receipt keeps failing expire like missing ones
+            Err(_) if expired => None,
+            Err(_) => continue,
+        };
+
+        // An earlier receipt in this poll may already have settled this entry.
+        if get_transaction_status(principal_id, chain_id, &stored.hash) != Some(TransactionStatus::Pending) {
+            continue;
+        }
+
+        if receipt.is_none() && !expired {
+            continue;
+        }
+
+        let recorded = with_chain_data(principal_id, chain_id, |chain_data| {
+            record_receipt(chain_data, chain_id, &stored.hash, receipt)
+        });
+        if recorded.is_ok() {
+            updated += 1;
+        }
+    }
+
+    updated
+}
+
+pub fn get_transaction_status(
+    principal_id: Principal,
+    chain_id: u64,
+    hash: &[u8],
+) -> Option<TransactionStatus> {
+    STATE.with(|s| {
+        let state = s.borrow();
+        state
+            .users
+            .get(&principal_id)?
+            .transactions
+            .get(&chain_id)?
+            .transactions
+            .iter()
+            .find(|tx| tx.hash == hash)
+            .map(|tx| tx.status)
+    })
+}
+
+#[cfg(feature = "rpc")]
+fn record_receipt(
+    chain_data: &mut TransactionChainData,
+    chain_id: u64,
+    hash: &[u8],
+    receipt: Option<(TransactionStatus, TransactionReceipt)>,
+) -> Result<(), String> {
+    let stored = chain_data
+        .transactions
+        .iter()
+        .find(|tx| tx.hash == hash)
+        .ok_or_else(|| "transaction not found".to_string())?;
+    let nonce = transaction::get_transaction(&stored.data, chain_id)?.get_nonce()?;
+
+    let (status, receipt) = match receipt {
+        Some((status, receipt)) => (status, Some(receipt)),
+        None => (TransactionStatus::Dropped, None),
+    };
+
+    for tx in chain_data.transactions.iter_mut() {
+        if tx.hash == hash {
+            tx.status = status;
+            tx.receipt = receipt.clone();
+        }
+    }
+
+    let same_nonce_pending = chain_data.transactions.iter().any(|tx| {
+        tx.status == TransactionStatus::Pending
+            && transaction::get_transaction(&tx.data, chain_id)
+                .and_then(|t| t.get_nonce())
+                .is_ok_and(|n| n == nonce)
+    });
+    // A dropped transaction gives its nonce back unless a replacement is still pending.
+    if receipt.is_none() && !same_nonce_pending {
+        chain_data.nonce_manager.drop_broadcast(nonce);
+    }
+
+    if receipt.is_some() {
+        chain_data.nonce_manager.confirm(nonce);
+
+        // A mined transaction makes every other transaction with its nonce invalid.
+        for tx in chain_data.transactions.iter_mut() {
+            let same_nonce = transaction::get_transaction(&tx.data, chain_id)
+                .and_then(|t| t.get_nonce())
+                .is_ok_and(|n| n == nonce);
+            if tx.status == TransactionStatus::Pending && same_nonce {
+                tx.status = TransactionStatus::Dropped;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends a signed transaction to the chain's RPC node and marks its nonce as broadcast.
+#[cfg(feature = "rpc")]
+pub async fn broadcast_transaction(
+    principal_id: Principal,
+    chain_id: u64,
+    signed_tx: Vec<u8>,
+    transport: &dyn RpcTransport,
+) -> Result<BroadcastTransactionResponse, String> {
+    let tx = transaction::get_transaction(&signed_tx, chain_id)?;
+    if !tx.is_signed() {
+        return Err("This is not a signed transaction".to_string());
+    }
+    let nonce = tx.get_nonce()?;
+
+    let chain_config = get_chain_config(chain_id)?;
+    let client = RpcClient::for_chain(transport, &chain_config)?;
+    let hash = client.send_raw_transaction(&signed_tx).await?;
+
+    mark_nonce_broadcast(principal_id, chain_id, nonce)?;
+
+    Ok(BroadcastTransactionResponse { hash })
+}
+
+/// Returns `None` only when the principal is not registered and the root key
+/// is not cached yet.
+pub fn get_caller_data(principal_id: Principal, chain_id: u64) -> Option<UserResponse> {
+    let address = get_address(principal_id).ok()?;
+
+    let users = STATE.with(|s| s.borrow().users.clone());
+    let transaction_data = users
+        .get(&principal_id)
+        .and_then(|user| user.transactions.get(&chain_id))
         .cloned()
         .unwrap_or_else(|| TransactionChainData::default());
 
@@ -294,30 +1474,72 @@ pub fn get_caller_data(principal_id: Principal, chain_id: u64) -> Option<UserRes
 pub fn clear_caller_history(principal_id: Principal, chain_id: u64) -> Result<(), String> {
     let users = STATE.with(|s| s.borrow().users.clone());
 
-    if let None = users.get(&principal_id) {
-        return Err("this user does not exist".to_string());
-    }
+    let result = if !users.contains_key(&principal_id) {
+        Err("this user does not exist".to_string())
+    } else {
+        STATE.with(|s| {
+            let mut state = s.borrow_mut();
+            let user = state.users.get_mut(&principal_id).unwrap();
+            let user_tx = user.transactions.get_mut(&chain_id);
+            if let Some(user_transactions) = user_tx {
+                user_transactions.transactions.clear();
+            }
+        });
+        Ok(())
+    };
 
-    STATE.with(|s| {
-        let mut state = s.borrow_mut();
-        let user = state.users.get_mut(&principal_id).unwrap();
-        let user_tx = user.transactions.get_mut(&chain_id);
-        if let Some(user_transactions) = user_tx {
-            user_transactions.transactions.clear();
-        }
+    audit(AuditEntry {
+        chain_id: Some(chain_id),
+        ..admin_entry(principal_id, AuditOperation::ClearCallerHistory, result.clone())
     });
+    result
+}
 
+/// Returns up to `limit` audit log entries starting at index `start`, oldest
+/// first. Only controllers can read the log.
+pub fn get_audit_log(principal_id: Principal, start: u64, limit: u64) -> Result<AuditLogPage, String> {
+    check_controller(principal_id)?;
+
+    Ok(AuditLogPage {
+        entries: audit::read_entries(start, limit)?,
+        total: audit::len(),
+        dropped: audit::dropped(),
+    })
+}
+
+/// Same as [`get_audit_log`] as CSV, with a header line.
+pub fn export_audit_log(principal_id: Principal, start: u64, limit: u64) -> Result<String, String> {
+    check_controller(principal_id)?;
+    audit::export_csv(start, limit)
+}
+
+fn check_controller(principal_id: Principal) -> Result<(), String> {
+    if !is_controller(principal_id) {
+        return Err("caller is not a controller".to_string());
+    }
     Ok(())
 }
 
+/// Writes the state after the audit log in stable memory.
 pub fn pre_upgrade() {
-    STATE.with(|s| {
-        ic_cdk::storage::stable_save((s,)).unwrap();
-    });
+    let state = STATE.with(|s| candid::encode_one(&*s.borrow())).unwrap();
+    audit::save_state(&state).unwrap();
 }
 
+/// Restores the state and the audit log. Canisters upgraded from a version
+/// without the log have their state saved with `stable_save` in the first
+/// release layout, which is migrated, and start an empty log.
 pub fn post_upgrade() {
-    let (s_prev,): (State,) = ic_cdk::storage::stable_restore().unwrap();
+    let s_prev = match audit::restore_state().unwrap() {
+        Some(state) => candid::decode_one(&state).unwrap(),
+        None => {
+            let bytes = audit::read_legacy_state();
+            let mut de = candid::de::IDLDeserialize::new(&bytes).unwrap();
+            let s_prev = de.get_value::<migration::StateV0>().unwrap();
+            audit::clear().unwrap();
+            State::from(s_prev)
+        }
+    };
     STATE.with(|s| {
         *s.borrow_mut() = s_prev;
     });
@@ -0,0 +1,144 @@
+use crate::nonce::NonceManager;
+use crate::state::{self, Environment};
+use crate::utils;
+use ic_cdk::export::{candid::CandidType, serde::Deserialize, Principal};
+use std::collections::HashMap;
+
+// State layout of the first release, kept as is to read stable memory written
+// by it. Candid rejects records missing a field that is not optional, so the
+// current `State` cannot decode it directly.
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransactionV0 {
+    pub data: Vec<u8>,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct TransactionChainDataV0 {
+    pub nonce: u64,
+    pub transactions: Vec<TransactionV0>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct UserDataV0 {
+    pub public_key: Vec<u8>,
+    pub transactions: HashMap<u64, TransactionChainDataV0>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct ConfigV0 {
+    pub env: Environment,
+    pub key_name: String,
+    pub sign_cycles: u64,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub struct StateV0 {
+    pub users: HashMap<Principal, UserDataV0>,
+    pub config: ConfigV0,
+}
+
+impl From<TransactionV0> for state::Transaction {
+    fn from(tx: TransactionV0) -> Self {
+        state::Transaction {
+            hash: utils::get_transaction_hash(&tx.data),
+            data: tx.data,
+            timestamp: tx.timestamp,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<TransactionChainDataV0> for state::TransactionChainData {
+    fn from(chain_data: TransactionChainDataV0) -> Self {
+        // The first release did not track broadcasts, so every signed nonce is
+        // taken as final. `resync_nonce` corrects it if some never landed.
+        let nonce_manager = NonceManager {
+            confirmed: chain_data.nonce,
+            ..Default::default()
+        };
+        state::TransactionChainData {
+            nonce: chain_data.nonce,
+            nonce_manager,
+            transactions: chain_data.transactions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<UserDataV0> for state::UserData {
+    fn from(user: UserDataV0) -> Self {
+        state::UserData {
+            public_key: user.public_key,
+            transactions: user
+                .transactions
+                .into_iter()
+                .map(|(chain_id, chain_data)| (chain_id, chain_data.into()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<StateV0> for state::State {
+    fn from(state: StateV0) -> Self {
+        state::State {
+            users: state.users.into_iter().map(|(id, user)| (id, user.into())).collect(),
+            config: state::Config {
+                env: state.config.env,
+                key_name: state.config.key_name,
+                sign_cycles: state.config.sign_cycles,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_state_migrates() {
+        let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+        let chain_data = TransactionChainDataV0 {
+            nonce: 3,
+            transactions: vec![TransactionV0 {
+                data: vec![0x02, 0xc0],
+                timestamp: 7,
+            }],
+        };
+        let baseline = StateV0 {
+            users: HashMap::from([(
+                principal_id,
+                UserDataV0 {
+                    public_key: vec![2; 33],
+                    transactions: HashMap::from([(1, chain_data)]),
+                },
+            )]),
+            config: ConfigV0 {
+                env: Environment::Staging,
+                key_name: "test_key_1".to_string(),
+                sign_cycles: 10,
+            },
+        };
+
+        // the current layout cannot read it
+        let bytes = candid::encode_one(&baseline).unwrap();
+        assert!(candid::decode_one::<state::State>(&bytes).is_err());
+
+        let decoded: StateV0 = candid::decode_one(&bytes).unwrap();
+        let state = state::State::from(decoded);
+        assert_eq!(state.config.key_name, "test_key_1");
+        assert_eq!(state.config.sign_cycles, 10);
+
+        let user = &state.users[&principal_id];
+        assert_eq!(user.cycles_balance, 0);
+        assert!(!user.raw_signing_enabled);
+        let chain_data = &user.transactions[&1];
+        assert_eq!(chain_data.nonce_manager.next_nonce(), 3);
+        assert_eq!(chain_data.transactions[0].timestamp, 7);
+        assert_eq!(chain_data.transactions[0].hash, utils::get_transaction_hash(&[0x02, 0xc0]));
+        assert_eq!(chain_data.transactions[0].status, state::TransactionStatus::Pending);
+    }
+}
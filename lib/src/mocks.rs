@@ -1,22 +1,117 @@
 use crate::ecdsa::reply::{ECDSAPublicKeyResponse, SignWithECDSAResponse};
-use crate::ecdsa::request::SignWithECDSA;
+use crate::ecdsa::request::{ECDSAPublicKey, SignWithECDSA};
+use crate::runtime::Runtime;
+use crate::signer::{LocalSigner, Signer};
 use crate::utils::string_to_vec_u8;
 use candid::de::IDLDeserialize;
 use candid::utils::{ArgumentDecoder, ArgumentEncoder};
 use candid::{Decode, Encode};
 use ic_cdk::api::call::{CallResult, RejectionCode};
+use ic_cdk::api::stable::StableMemoryError;
 use ic_cdk::export::Principal;
-use libsecp256k1_test::{PublicKey, SecretKey};
 use std::cell::RefCell;
 use std::future::Future;
-
-#[derive(Clone, Default)]
-struct StateTest {
-    private_key: String,
+#[cfg(feature = "rpc")]
+use crate::rpc::{RpcFuture, RpcTransport};
+#[cfg(feature = "rpc")]
+use serde_json::{json, Value};
+#[cfg(feature = "rpc")]
+use std::collections::HashMap;
+
+const PRIVATE_KEY: &str = "5c86d3784f39013aa50aada6d97f9bad733636d57bf6bb18b0bca1ffcff374b4";
+const CHAIN_CODE: &str = "a2c3a5f1e40b1fd8c6b1b64e1c9c2c5bb0bb21e54d0f6cfb0e1bbd8b4b9d5d2e";
+
+fn local_signer() -> LocalSigner {
+    LocalSigner::with_chain_code(&string_to_vec_u8(PRIVATE_KEY), string_to_vec_u8(CHAIN_CODE)).unwrap()
 }
 
 thread_local! {
-    static STATE_TEST: RefCell<StateTest> = RefCell::new(StateTest::default());
+    static STABLE_MEMORY: RefCell<Vec<u8>> = const { RefCell::new(vec![]) };
+    static MSG_CYCLES: RefCell<u64> = const { RefCell::new(0) };
+    static CYCLES_REFUNDED: RefCell<u64> = const { RefCell::new(0) };
+}
+
+/// Attaches cycles to the current message, for `deposit_cycles`.
+pub fn attach_cycles(amount: u64) {
+    MSG_CYCLES.with(|c| *c.borrow_mut() = amount);
+}
+
+/// Sets the cycles refunded by every `sign_with_ecdsa` call.
+pub fn refund_cycles(amount: u64) {
+    CYCLES_REFUNDED.with(|c| *c.borrow_mut() = amount);
+}
+
+pub fn msg_cycles_available() -> u64 {
+    MSG_CYCLES.with(|c| *c.borrow())
+}
+
+pub fn msg_cycles_accept(max_amount: u64) -> u64 {
+    MSG_CYCLES.with(|c| {
+        let accepted = max_amount.min(*c.borrow());
+        *c.borrow_mut() -= accepted;
+        accepted
+    })
+}
+
+pub fn msg_cycles_refunded() -> u64 {
+    CYCLES_REFUNDED.with(|c| *c.borrow())
+}
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+pub fn stable64_size() -> u64 {
+    STABLE_MEMORY.with(|m| m.borrow().len() as u64 / WASM_PAGE_SIZE)
+}
+
+pub fn stable64_grow(new_pages: u64) -> Result<u64, StableMemoryError> {
+    let previous = stable64_size();
+    STABLE_MEMORY.with(|m| m.borrow_mut().resize(((previous + new_pages) * WASM_PAGE_SIZE) as usize, 0));
+    Ok(previous)
+}
+
+pub fn stable64_write(offset: u64, buf: &[u8]) {
+    STABLE_MEMORY.with(|m| m.borrow_mut()[offset as usize..offset as usize + buf.len()].copy_from_slice(buf));
+}
+
+pub fn stable64_read(offset: u64, buf: &mut [u8]) {
+    STABLE_MEMORY.with(|m| buf.copy_from_slice(&m.borrow()[offset as usize..offset as usize + buf.len()]));
+}
+
+/// Runtime over the thread-local stand-ins below, the default in unit tests.
+pub struct TestRuntime;
+
+impl Runtime for TestRuntime {
+    fn time(&self) -> u64 {
+        ic_timestamp()
+    }
+
+    fn stable64_size(&self) -> u64 {
+        stable64_size()
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        stable64_grow(new_pages)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        stable64_read(offset, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        stable64_write(offset, buf)
+    }
+
+    fn msg_cycles_available(&self) -> u64 {
+        msg_cycles_available()
+    }
+
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        msg_cycles_accept(max_amount)
+    }
+
+    fn msg_cycles_refunded(&self) -> u64 {
+        msg_cycles_refunded()
+    }
 }
 
 pub fn ic_timestamp() -> u64 {
@@ -32,51 +127,104 @@ pub fn ic_call<T: ArgumentEncoder, R: for<'a> ArgumentDecoder<'a>>(
     let args_raw = candid::encode_args(args).expect("Failed to encode arguments.");
 
     async move {
-        if method == "ecdsa_public_key" {
-            let private_key_state = STATE_TEST.with(|s| {
-                let mut state = s.borrow_mut();
-                state.private_key = String::from("5c86d3784f39013aa50aada6d97f9bad733636d57bf6bb18b0bca1ffcff374b4");
-                state.private_key.clone()
-            });
-
-            let private_key = SecretKey::parse_slice(&string_to_vec_u8(&private_key_state)).unwrap();
-
-            let public_key = PublicKey::from_secret_key(&private_key).serialize_compressed();
+        let bytes = match method {
+            "ecdsa_public_key" => {
+                let args = Decode!(&args_raw, ECDSAPublicKey).unwrap();
+                let key = local_signer().public_key(args.derivation_path).await.unwrap();
+
+                Encode!(&ECDSAPublicKeyResponse {
+                    public_key: key.public_key,
+                    chain_code: key.chain_code,
+                })
+                .unwrap()
+            }
+            "sign_with_ecdsa" => {
+                let args = Decode!(&args_raw, SignWithECDSA).unwrap();
+                let signature = local_signer()
+                    .sign_digest(args.derivation_path, args.message_hash)
+                    .await
+                    .unwrap();
+
+                Encode!(&SignWithECDSAResponse { signature }).unwrap()
+            }
+            _ => return Err((RejectionCode::CanisterReject, String::from("no method"))),
+        };
+
+        let mut de = IDLDeserialize::new(&bytes).unwrap();
+        let res_decoded: R = ArgumentDecoder::decode(&mut de).unwrap();
+        Ok(res_decoded)
+    }
+}
 
-            let res = ECDSAPublicKeyResponse {
-                public_key: public_key.to_vec(),
-                chain_code: vec![0, 1],
-            };
+/// In-process JSON-RPC node answering each method with a canned body.
+#[cfg(feature = "rpc")]
+#[derive(Default)]
+pub struct StubTransport {
+    responses: HashMap<String, String>,
+    provider_responses: HashMap<(String, String), String>,
+    params_responses: HashMap<(String, String), String>,
+    pub requests: RefCell<Vec<(String, Value)>>,
+    /// Limits passed to `post_with_limit`.
+    pub response_limits: RefCell<Vec<u64>>,
+}
 
-            let bytes = Encode!(&res).unwrap();
-            let mut de = IDLDeserialize::new(&bytes).unwrap();
-            let res_decoded: R = ArgumentDecoder::decode(&mut de).unwrap();
+#[cfg(feature = "rpc")]
+impl StubTransport {
+    pub fn with_result(self, method: &str, result: Value) -> Self {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "result": result });
+        self.with_raw_response(method, &body.to_string())
+    }
 
-            return Ok(res_decoded);
-        }
-        if method == "sign_with_ecdsa" {
-            let private_key_state = STATE_TEST.with(|s| s.borrow().private_key.clone());
-            let private_key =
-                SecretKey::parse_slice(&string_to_vec_u8(&private_key_state)).unwrap();
+    pub fn with_error(self, method: &str, message: &str) -> Self {
+        self.with_error_code(method, -32000, message)
+    }
 
-            let args = Decode!(&args_raw, SignWithECDSA).unwrap();
+    pub fn with_error_code(self, method: &str, code: i64, message: &str) -> Self {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": code, "message": message } });
+        self.with_raw_response(method, &body.to_string())
+    }
 
-            let message: [u8; 32] = args.message_hash[..32].try_into().unwrap();
+    pub fn with_raw_response(mut self, method: &str, body: &str) -> Self {
+        self.responses.insert(method.to_string(), body.to_string());
+        self
+    }
 
-            let message_parsed = libsecp256k1_test::Message::parse(&message);
+    /// Overrides the result of `method` when called with `params`.
+    pub fn with_params_result(mut self, method: &str, params: Value, result: Value) -> Self {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "result": result });
+        self.params_responses
+            .insert((method.to_string(), params.to_string()), body.to_string());
+        self
+    }
 
-            let signature = libsecp256k1_test::sign(&message_parsed, &private_key);
+    /// Overrides the result of `method` for a single provider.
+    pub fn with_provider_result(mut self, url: &str, method: &str, result: Value) -> Self {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "result": result });
+        self.provider_responses
+            .insert((url.to_string(), method.to_string()), body.to_string());
+        self
+    }
+}
 
-            let res = SignWithECDSAResponse {
-                signature: signature.0.serialize().to_vec(),
-            };
-            let bytes = Encode!(&res).unwrap();
-            let mut de = IDLDeserialize::new(&bytes).unwrap();
-            let res_decoded: R = ArgumentDecoder::decode(&mut de).unwrap();
+#[cfg(feature = "rpc")]
+impl RpcTransport for StubTransport {
+    fn post(&self, url: &str, body: Vec<u8>) -> RpcFuture<'_> {
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let method = request["method"].as_str().unwrap().to_string();
+        self.requests.borrow_mut().push((url.to_string(), request.clone()));
+
+        let response = self
+            .provider_responses
+            .get(&(url.to_string(), method.clone()))
+            .or_else(|| self.params_responses.get(&(method.clone(), request["params"].to_string())))
+            .or_else(|| self.responses.get(&method))
+            .map(|body| body.clone().into_bytes())
+            .ok_or(format!("no stub for {}", method));
+        Box::pin(async move { response })
+    }
 
-            return Ok(res_decoded);
-        } else {
-            return Err((RejectionCode::CanisterReject, String::from("no method")));
-        }
+    fn post_with_limit(&self, url: &str, body: Vec<u8>, max_response_bytes: u64) -> RpcFuture<'_> {
+        self.response_limits.borrow_mut().push(max_response_bytes);
+        self.post(url, body)
     }
 }
@@ -0,0 +1,243 @@
+use ic_cdk::export::{
+    candid::CandidType,
+    serde::{Deserialize, Serialize},
+};
+use std::collections::BTreeMap;
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum NonceStatus {
+    Reserved,
+    Signed,
+    Broadcast,
+    Confirmed,
+}
+
+/// Tracks the nonces of one address on one chain.
+///
+/// Every nonce below `confirmed` is final on chain. Nonces at or above it are
+/// either free or present in `pending` with the step they reached locally.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct NonceManager {
+    pub confirmed: u64,
+    pub pending: BTreeMap<u64, NonceStatus>,
+}
+
+impl NonceManager {
+    /// Lowest nonce that is neither confirmed nor pending.
+    pub fn next_nonce(&self) -> u64 {
+        let mut nonce = self.confirmed;
+        while self.pending.contains_key(&nonce) {
+            nonce += 1;
+        }
+        nonce
+    }
+
+    pub fn status(&self, nonce: u64) -> Option<NonceStatus> {
+        if nonce < self.confirmed {
+            return Some(NonceStatus::Confirmed);
+        }
+        self.pending.get(&nonce).copied()
+    }
+
+    pub fn reserve(&mut self) -> u64 {
+        let nonce = self.next_nonce();
+        self.pending.insert(nonce, NonceStatus::Reserved);
+        nonce
+    }
+
+    /// Checks that a transaction with `nonce` can be signed without
+    /// colliding with another transaction or leaving a gap.
+    pub fn check(&self, nonce: u64) -> Result<(), String> {
+        match self.status(nonce) {
+            Some(NonceStatus::Reserved) => Ok(()),
+            Some(NonceStatus::Confirmed) => Err(format!("nonce {} is already confirmed", nonce)),
+            Some(_) => Err(format!("nonce {} is already in use", nonce)),
+            None => {
+                let expected = self.next_nonce();
+                if nonce != expected {
+                    return Err(format!("nonce {} leaves a gap, expected {}", nonce, expected));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reserves a specific nonce after checking it. Returns `true` when the
+    /// nonce was not reserved before, so the caller knows to release it on failure.
+    pub fn claim(&mut self, nonce: u64) -> Result<bool, String> {
+        self.check(nonce)?;
+        let claimed = self.pending.insert(nonce, NonceStatus::Reserved).is_none();
+        Ok(claimed)
+    }
+
+    pub fn mark_signed(&mut self, nonce: u64) {
+        if nonce >= self.confirmed {
+            self.pending.insert(nonce, NonceStatus::Signed);
+        }
+    }
+
+    pub fn mark_broadcast(&mut self, nonce: u64) -> Result<(), String> {
+        match self.status(nonce) {
+            Some(NonceStatus::Signed) | Some(NonceStatus::Broadcast) => {
+                self.pending.insert(nonce, NonceStatus::Broadcast);
+                Ok(())
+            }
+            _ => Err(format!("nonce {} has not been signed", nonce)),
+        }
+    }
+
+    /// Marks `nonce` and every nonce below it as confirmed.
+    pub fn confirm(&mut self, nonce: u64) {
+        if nonce >= self.confirmed {
+            self.resync(nonce + 1);
+        }
+    }
+
+    /// Gives back a nonce that will never reach the chain.
+    pub fn release(&mut self, nonce: u64) -> Result<(), String> {
+        match self.status(nonce) {
+            Some(NonceStatus::Reserved) | Some(NonceStatus::Signed) => {
+                self.pending.remove(&nonce);
+                Ok(())
+            }
+            Some(NonceStatus::Broadcast) => Err(format!("nonce {} was already broadcast", nonce)),
+            Some(NonceStatus::Confirmed) => Err(format!("nonce {} is already confirmed", nonce)),
+            None => Err(format!("nonce {} is not pending", nonce)),
+        }
+    }
+
+    /// Gives back a broadcast nonce whose transactions were dropped by the chain.
+    pub fn drop_broadcast(&mut self, nonce: u64) {
+        if self.status(nonce) == Some(NonceStatus::Broadcast) {
+            self.pending.remove(&nonce);
+        }
+    }
+
+    /// Aligns the manager with the transaction count reported by the chain.
+    pub fn resync(&mut self, transaction_count: u64) {
+        self.confirmed = transaction_count;
+        self.pending = self.pending.split_off(&transaction_count);
+    }
+
+    /// Aligns the manager with the `latest` and `pending` transaction counts
+    /// of the chain. Nonces in between are in the mempool and stay replaceable.
+    /// Local entries at or above `pending` are kept: signed transactions may
+    /// not be broadcast yet and are only given back by [`NonceManager::release`],
+    /// and broadcast ones by the receipt timeout.
+    pub fn sync(&mut self, latest: u64, pending: u64) {
+        let pending = pending.max(latest);
+        self.resync(latest);
+        for nonce in latest..pending {
+            self.pending.insert(nonce, NonceStatus::Broadcast);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_sequential() {
+        let mut nonces = NonceManager::default();
+        assert_eq!(nonces.reserve(), 0);
+        assert_eq!(nonces.reserve(), 1);
+        assert_eq!(nonces.next_nonce(), 2);
+    }
+
+    #[test]
+    fn release_reuses_gap() {
+        let mut nonces = NonceManager::default();
+        nonces.reserve();
+        nonces.reserve();
+        nonces.release(0).unwrap();
+        assert_eq!(nonces.next_nonce(), 0);
+        assert_eq!(nonces.reserve(), 0);
+        assert_eq!(nonces.next_nonce(), 2);
+    }
+
+    #[test]
+    fn check_conflicts() {
+        let mut nonces = NonceManager::default();
+        nonces.resync(3);
+        nonces.mark_signed(3);
+
+        assert_eq!(nonces.check(2), Err("nonce 2 is already confirmed".to_string()));
+        assert_eq!(nonces.check(3), Err("nonce 3 is already in use".to_string()));
+        assert_eq!(nonces.check(5), Err("nonce 5 leaves a gap, expected 4".to_string()));
+        assert_eq!(nonces.check(4), Ok(()));
+    }
+
+    #[test]
+    fn claim_reserved_nonce() {
+        let mut nonces = NonceManager::default();
+        let nonce = nonces.reserve();
+        assert_eq!(nonces.claim(nonce), Ok(false));
+        assert_eq!(nonces.claim(1), Ok(true));
+        assert_eq!(nonces.status(1), Some(NonceStatus::Reserved));
+    }
+
+    #[test]
+    fn broadcast_cannot_be_released() {
+        let mut nonces = NonceManager::default();
+        let nonce = nonces.reserve();
+        assert!(nonces.mark_broadcast(nonce).is_err());
+
+        nonces.mark_signed(nonce);
+        nonces.mark_broadcast(nonce).unwrap();
+        assert_eq!(nonces.release(nonce), Err("nonce 0 was already broadcast".to_string()));
+    }
+
+    #[test]
+    fn confirm_and_resync() {
+        let mut nonces = NonceManager::default();
+        for _ in 0..4 {
+            let nonce = nonces.reserve();
+            nonces.mark_signed(nonce);
+        }
+        nonces.confirm(1);
+        assert_eq!(nonces.confirmed, 2);
+        assert_eq!(nonces.status(1), Some(NonceStatus::Confirmed));
+        assert_eq!(nonces.status(2), Some(NonceStatus::Signed));
+
+        nonces.resync(10);
+        assert!(nonces.pending.is_empty());
+        assert_eq!(nonces.next_nonce(), 10);
+    }
+
+    #[test]
+    fn sync_keeps_mempool_nonces_replaceable() {
+        let mut nonces = NonceManager::default();
+        for _ in 0..4 {
+            let nonce = nonces.reserve();
+            nonces.mark_signed(nonce);
+        }
+        nonces.sync(1, 3);
+
+        assert_eq!(nonces.status(0), Some(NonceStatus::Confirmed));
+        assert_eq!(nonces.status(1), Some(NonceStatus::Broadcast));
+        assert_eq!(nonces.status(2), Some(NonceStatus::Broadcast));
+        assert_eq!(nonces.status(3), Some(NonceStatus::Signed));
+        assert_eq!(nonces.next_nonce(), 4);
+    }
+
+    #[test]
+    fn sync_keeps_unbroadcast_signed_nonces() {
+        let mut nonces = NonceManager::default();
+        for _ in 0..3 {
+            let nonce = nonces.reserve();
+            nonces.mark_signed(nonce);
+        }
+        let reserved = nonces.reserve();
+        nonces.sync(0, 1);
+
+        assert_eq!(nonces.status(0), Some(NonceStatus::Broadcast));
+        assert_eq!(nonces.status(1), Some(NonceStatus::Signed));
+        assert_eq!(nonces.status(2), Some(NonceStatus::Signed));
+        assert_eq!(nonces.status(reserved), Some(NonceStatus::Reserved));
+        assert_eq!(nonces.reserve(), 4);
+
+        nonces.release(1).unwrap();
+        assert_eq!(nonces.reserve(), 1);
+    }
+}
@@ -0,0 +1,940 @@
+use crate::ic_call;
+use crate::state::{ChainConfig, FeeEstimationConfig, TransactionReceipt, TransactionStatus};
+use crate::types::{Address, Bytes, H256};
+use crate::utils::{check_rpc_providers, get_balance_of_data, get_transaction_hash};
+use crate::TransactionFees;
+use futures::future::join_all;
+use ic_cdk::api::management_canister::http_request::{
+    CanisterHttpRequestArgument, HttpHeader, HttpMethod, HttpResponse, TransformFunc,
+    TransformType,
+};
+use ic_cdk::export::Principal;
+use primitive_types::U256;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Error returned when a provider does not implement the requested method.
+pub const METHOD_NOT_SUPPORTED: &str = "RPC method is not supported";
+
+// JSON-RPC 2.0 "Method not found" error code.
+const METHOD_NOT_FOUND_CODE: i64 = -32601;
+
+// Message every error listed in `KNOWN_TRANSACTION_ERRORS` is rewritten to.
+const ALREADY_KNOWN: &str = "already known";
+
+// Errors of `eth_sendRawTransaction` for a transaction a node has already seen.
+// Replicas racing to broadcast the same transaction get these instead of its
+// hash, in wording that differs between clients and replicas.
+const KNOWN_TRANSACTION_ERRORS: [&str; 4] = ["already known", "known transaction", "already imported", "nonce too low"];
+
+pub type RpcFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, String>> + 'a>>;
+
+/// Sends a JSON-RPC request body to `url` and returns the raw response body.
+///
+/// Implement this to route requests through something other than HTTPS outcalls,
+/// e.g. an in-process stub in unit tests.
+pub trait RpcTransport {
+    fn post(&self, url: &str, body: Vec<u8>) -> RpcFuture<'_>;
+
+    /// Same as [`RpcTransport::post`] with a response size limit set by the
+    /// caller, e.g. from the chain config. Transports without limits can
+    /// ignore it.
+    fn post_with_limit(&self, url: &str, body: Vec<u8>, _max_response_bytes: u64) -> RpcFuture<'_> {
+        self.post(url, body)
+    }
+}
+
+thread_local! {
+    static TRANSPORT: RefCell<Rc<dyn RpcTransport>> = RefCell::new(Rc::new(HttpOutcallTransport::default()));
+}
+
+/// Sets the transport used by features that talk to RPC nodes on their own,
+/// such as the automatic nonce sync. It is not persisted across upgrades.
+pub fn set_transport(transport: Rc<dyn RpcTransport>) {
+    TRANSPORT.with(|t| *t.borrow_mut() = transport);
+}
+
+pub fn get_transport() -> Rc<dyn RpcTransport> {
+    TRANSPORT.with(|t| t.borrow().clone())
+}
+
+/// Transport that sends requests through the management canister `http_request` method.
+#[derive(Debug, Clone)]
+pub struct HttpOutcallTransport {
+    /// Response size limit of methods missing from `method_max_response_bytes`.
+    pub max_response_bytes: u64,
+    /// Response size limit per JSON-RPC method, for methods whose responses
+    /// are larger than the default, e.g. fee history. Limits of receipts can
+    /// also be raised per chain with `ChainConfig::receipt_max_response_bytes`.
+    pub method_max_response_bytes: HashMap<String, u64>,
+    /// Name of a query method of this canister used to strip non-deterministic
+    /// response headers, see [`transform`].
+    pub transform_method: Option<String>,
+}
+
+impl Default for HttpOutcallTransport {
+    fn default() -> Self {
+        HttpOutcallTransport {
+            max_response_bytes: 8_192,
+            method_max_response_bytes: HashMap::from([("eth_feeHistory".to_string(), 64 * 1024)]),
+            transform_method: None,
+        }
+    }
+}
+
+impl HttpOutcallTransport {
+    /// Response size limit for the JSON-RPC request `body`.
+    pub fn response_bytes_limit(&self, body: &[u8]) -> u64 {
+        serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|request| {
+                let method = request["method"].as_str()?;
+                self.method_max_response_bytes.get(method).copied()
+            })
+            .unwrap_or(self.max_response_bytes)
+    }
+}
+
+impl RpcTransport for HttpOutcallTransport {
+    fn post(&self, url: &str, body: Vec<u8>) -> RpcFuture<'_> {
+        let max_response_bytes = self.response_bytes_limit(&body);
+        self.post_with_limit(url, body, max_response_bytes)
+    }
+
+    fn post_with_limit(&self, url: &str, body: Vec<u8>, max_response_bytes: u64) -> RpcFuture<'_> {
+        let transform = self.transform_method.as_ref().map(|method| {
+            TransformType::Function(TransformFunc(candid::Func {
+                principal: ic_cdk::id(),
+                method: method.clone(),
+            }))
+        });
+
+        let request = CanisterHttpRequestArgument {
+            url: url.to_string(),
+            max_response_bytes: Some(max_response_bytes),
+            method: HttpMethod::POST,
+            headers: vec![HttpHeader {
+                name: "Content-Type".to_string(),
+                value: "application/json".to_string(),
+            }],
+            body: Some(body),
+            transform,
+        };
+        let cycles = http_request_cycles(&request);
+
+        Box::pin(async move {
+            let (response,): (HttpResponse,) = ic_call(
+                Principal::management_canister(),
+                "http_request",
+                (request,),
+                cycles,
+            )
+            .await
+            .map_err(|e| format!("Failed to call http_request {}", e.1))?;
+
+            if response.status != 200 {
+                return Err(format!("RPC request failed with status {}", response.status));
+            }
+
+            Ok(response.body)
+        })
+    }
+}
+
+/// Transform function for HTTPS outcalls. Replicas must agree on the response,
+/// so headers such as `Date` are dropped and only status and body are kept.
+/// Errors for an already broadcast transaction are rewritten to the same body,
+/// which [`RpcClient::send_raw_transaction`] treats as success.
+pub fn transform(response: HttpResponse) -> HttpResponse {
+    let known_transaction = serde_json::from_slice::<Value>(&response.body)
+        .ok()
+        .and_then(|body| body["error"]["message"].as_str().map(is_known_transaction_error))
+        .unwrap_or(false);
+    let body = if known_transaction {
+        json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32000, "message": ALREADY_KNOWN } })
+            .to_string()
+            .into_bytes()
+    } else {
+        response.body
+    };
+
+    HttpResponse {
+        status: response.status,
+        headers: vec![],
+        body,
+    }
+}
+
+fn is_known_transaction_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    KNOWN_TRANSACTION_ERRORS.iter().any(|error| message.contains(error))
+}
+
+// Same formula the ic-cdk `http_request` helper uses to attach cycles.
+fn http_request_cycles(request: &CanisterHttpRequestArgument) -> u64 {
+    let request_bytes = candid::encode_args((request,)).map_or(0, |bytes| bytes.len() as u64);
+    let max_response_bytes = request.max_response_bytes.unwrap_or(2 * 1024 * 1024);
+
+    400_000_000 + 100_000 * (request_bytes + "http_request".len() as u64 + max_response_bytes)
+}
+
+/// Client that sends every request to a set of providers and only accepts a
+/// result returned by at least `threshold` of them.
+pub struct RpcClient<'a> {
+    transport: &'a dyn RpcTransport,
+    providers: Vec<String>,
+    threshold: usize,
+    receipt_max_response_bytes: Option<u64>,
+}
+
+impl<'a> RpcClient<'a> {
+    pub fn new(transport: &'a dyn RpcTransport, url: &str) -> Self {
+        RpcClient {
+            transport,
+            providers: vec![url.to_string()],
+            threshold: 1,
+            receipt_max_response_bytes: None,
+        }
+    }
+
+    pub fn with_providers(
+        transport: &'a dyn RpcTransport,
+        providers: &[String],
+        threshold: u64,
+    ) -> Result<Self, String> {
+        check_rpc_providers(providers, threshold)?;
+
+        Ok(RpcClient {
+            transport,
+            providers: providers.to_vec(),
+            threshold: threshold as usize,
+            receipt_max_response_bytes: None,
+        })
+    }
+
+    pub fn for_chain(transport: &'a dyn RpcTransport, chain_config: &ChainConfig) -> Result<Self, String> {
+        Ok(RpcClient {
+            receipt_max_response_bytes: chain_config.receipt_max_response_bytes,
+            ..Self::with_providers(transport, &chain_config.rpc_providers, chain_config.rpc_threshold)?
+        })
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.consensus(method, params, Ok).await
+    }
+
+    /// Queries every provider and parses each response with `parse`. Parsed
+    /// results are compared, so fields ignored by `parse` cannot cause a
+    /// disagreement.
+    async fn consensus<T, F>(&self, method: &str, params: Value, parse: F) -> Result<T, String>
+    where
+        T: PartialEq,
+        F: Fn(Value) -> Result<T, String>,
+    {
+        self.consensus_with(method, params, None, |response| response.and_then(&parse)).await
+    }
+
+    /// Same as [`RpcClient::consensus`], with the response size limit of the
+    /// transport replaced by `max_response_bytes` when it is set. `parse` also
+    /// receives provider errors, so it can turn some of them into results.
+    async fn consensus_with<T, F>(
+        &self,
+        method: &str,
+        params: Value,
+        max_response_bytes: Option<u64>,
+        parse: F,
+    ) -> Result<T, String>
+    where
+        T: PartialEq,
+        F: Fn(Result<Value, String>) -> Result<T, String>,
+    {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let body = serde_json::to_vec(&request).map_err(|e| e.to_string())?;
+
+        let responses = join_all(
+            self.providers
+                .iter()
+                .map(|url| self.call_provider(url, body.clone(), max_response_bytes)),
+        )
+        .await;
+
+        let mut first_error = None;
+        let mut groups: Vec<(T, usize)> = vec![];
+        for response in responses {
+            match parse(response) {
+                Ok(result) => match groups.iter_mut().find(|(value, _)| *value == result) {
+                    Some((_, count)) => *count += 1,
+                    None => groups.push((result, 1)),
+                },
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        let successes: usize = groups.iter().map(|(_, count)| count).sum();
+        // With a threshold of half the providers or less, two different results
+        // can both reach it. Neither is accepted then.
+        let mut agreed = groups.into_iter().filter(|(_, count)| *count >= self.threshold);
+        match (agreed.next(), agreed.next()) {
+            (Some((result, _)), None) => return Ok(result),
+            (Some(_), Some(_)) => return Err(format!("RPC providers disagree on {}", method)),
+            _ => {}
+        }
+        match first_error {
+            Some(e) if successes < self.threshold => Err(e),
+            _ => Err(format!("RPC providers disagree on {}", method)),
+        }
+    }
+
+    async fn call_provider(
+        &self,
+        url: &str,
+        body: Vec<u8>,
+        max_response_bytes: Option<u64>,
+    ) -> Result<Value, String> {
+        let response = match max_response_bytes {
+            Some(limit) => self.transport.post_with_limit(url, body, limit).await?,
+            None => self.transport.post(url, body).await?,
+        };
+        let response: Value = serde_json::from_slice(&response)
+            .map_err(|e| format!("Invalid RPC response {}", e))?;
+
+        if let Some(error) = response.get("error") {
+            if error.get("code").and_then(Value::as_i64) == Some(METHOD_NOT_FOUND_CODE) {
+                return Err(METHOD_NOT_SUPPORTED.to_string());
+            }
+            let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+            return Err(format!("RPC error {}", message));
+        }
+
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| "Invalid RPC response missing result".to_string())
+    }
+
+    /// Broadcasts a signed transaction and returns its hash. A node that
+    /// already knows the transaction, or has mined its nonce, counts as
+    /// success, since every replica but the first gets such an error.
+    pub async fn send_raw_transaction(&self, signed_tx: &[u8]) -> Result<H256, String> {
+        let raw_tx = Bytes(signed_tx.to_vec()).to_string();
+        let hash = H256::try_from(&get_transaction_hash(signed_tx)[..])?;
+        self.consensus_with("eth_sendRawTransaction", json!([raw_tx]), None, |response| match response {
+            Err(e) if is_known_transaction_error(&e) => Ok(hash),
+            response => response?
+                .as_str()
+                .and_then(|hash| hash.parse().ok())
+                .ok_or_else(|| "Invalid transaction hash".to_string()),
+        })
+        .await
+    }
+
+    pub async fn get_transaction_count(&self, address: &Address, block: &str) -> Result<u64, String> {
+        self.consensus("eth_getTransactionCount", json!([address, block]), |result| {
+            let count = parse_quantity(&result)?;
+            if count > U256::from(u64::MAX) {
+                return Err("Invalid transaction count".to_string());
+            }
+
+            Ok(count.as_u64())
+        })
+        .await
+    }
+
+    /// Returns `None` while the transaction is not mined.
+    pub async fn get_transaction_receipt(
+        &self,
+        hash: &H256,
+    ) -> Result<Option<(TransactionStatus, TransactionReceipt)>, String> {
+        let limit = self.receipt_max_response_bytes;
+        self.consensus_with("eth_getTransactionReceipt", json!([hash]), limit, |response| {
+            response.and_then(parse_receipt)
+        })
+        .await
+    }
+
+    pub async fn get_balance(&self, address: &Address, block: &str) -> Result<U256, String> {
+        self.consensus("eth_getBalance", json!([address, block]), |result| parse_quantity(&result)).await
+    }
+
+    /// Executes a read-only call and returns the raw return data.
+    pub async fn eth_call(&self, call: Value, block: &str) -> Result<Vec<u8>, String> {
+        self.consensus("eth_call", json!([call, block]), |result| {
+            result
+                .as_str()
+                .filter(|data| data.starts_with("0x"))
+                .and_then(|data| data.parse::<Bytes>().ok())
+                .map(|data| data.0)
+                .ok_or_else(|| "Invalid call result".to_string())
+        })
+        .await
+    }
+
+    pub async fn erc_20_balance_of(&self, contract_address: &Address, owner: &Address) -> Result<U256, String> {
+        let call = json!({
+            "to": contract_address,
+            "data": Bytes(get_balance_of_data(owner)),
+        });
+        let result = self.eth_call(call, "latest").await?;
+        if result.len() != 32 {
+            return Err("Invalid balanceOf result".to_string());
+        }
+
+        Ok(U256::from_big_endian(&result))
+    }
+
+    pub async fn gas_price(&self) -> Result<U256, String> {
+        self.consensus("eth_gasPrice", json!([]), |result| parse_quantity(&result)).await
+    }
+
+    pub async fn estimate_gas(&self, call: Value) -> Result<u64, String> {
+        self.consensus("eth_estimateGas", json!([call]), |result| {
+            let gas = parse_quantity(&result)?;
+            if gas > U256::from(u64::MAX) {
+                return Err("Invalid gas estimate".to_string());
+            }
+
+            Ok(gas.as_u64())
+        })
+        .await
+    }
+
+    /// Returns the base fee of the next block and the median priority fee paid
+    /// at `percentile` over the last `blocks` blocks.
+    pub async fn fee_history(&self, blocks: u64, percentile: u8) -> Result<(U256, U256), String> {
+        let params = json!([format!("{:#x}", blocks), "latest", [percentile]]);
+        self.consensus("eth_feeHistory", params, parse_fee_history).await
+    }
+
+    /// Estimates fees from `eth_feeHistory`, falling back to `eth_gasPrice` on
+    /// chains without a base fee or without `eth_feeHistory`, and the gas limit
+    /// from `eth_estimateGas`. Any other `eth_feeHistory` error is returned.
+    pub async fn estimate_fees(
+        &self,
+        config: &FeeEstimationConfig,
+        call: Value,
+    ) -> Result<TransactionFees, String> {
+        let fee_history = match self
+            .fee_history(config.fee_history_blocks, config.priority_fee_percentile)
+            .await
+        {
+            Err(e) if e == METHOD_NOT_SUPPORTED => None,
+            result => Some(result?),
+        };
+        // without a base fee the chain may not accept EIP-1559 transactions
+        let (max_priority_fee_per_gas, max_fee_per_gas, legacy) = match fee_history {
+            Some((base_fee, priority_fee)) if !base_fee.is_zero() => {
+                let max_fee = apply_multiplier(base_fee, config.base_fee_multiplier_percent)? + priority_fee;
+                (priority_fee, max_fee, false)
+            }
+            _ => {
+                let gas_price = self.gas_price().await?;
+                (gas_price, gas_price, true)
+            }
+        };
+
+        let gas = self.estimate_gas(call).await?;
+        let gas_limit = apply_multiplier(U256::from(gas), config.gas_limit_multiplier_percent)?;
+        if gas_limit > U256::from(u64::MAX) {
+            return Err("Invalid gas estimate".to_string());
+        }
+
+        Ok(TransactionFees {
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit: gas_limit.as_u64(),
+            legacy,
+        })
+    }
+}
+
+/// Builds the call object used by `eth_estimateGas` and `eth_call`.
+pub fn transaction_call(from: &Address, to: Option<&Address>, data: &[u8], value: U256) -> Value {
+    let mut call = json!({
+        "from": from,
+        "data": Bytes(data.to_vec()),
+        "value": format!("{:#x}", value),
+    });
+    if let Some(to) = to {
+        call["to"] = json!(to);
+    }
+    call
+}
+
+fn parse_receipt(result: Value) -> Result<Option<(TransactionStatus, TransactionReceipt)>, String> {
+    if result.is_null() {
+        return Ok(None);
+    }
+
+    let status = if parse_quantity(&result["status"])?.is_zero() {
+        TransactionStatus::Reverted
+    } else {
+        TransactionStatus::Success
+    };
+    let block_number = parse_quantity(&result["blockNumber"])?;
+    let gas_used = parse_quantity(&result["gasUsed"])?;
+    let effective_gas_price = match &result["effectiveGasPrice"] {
+        Value::Null => None,
+        price => Some(parse_quantity(price)?),
+    };
+    if block_number > U256::from(u64::MAX)
+        || gas_used > U256::from(u64::MAX)
+        || effective_gas_price.is_some_and(|price| price > U256::from(u128::MAX))
+    {
+        return Err("Invalid transaction receipt".to_string());
+    }
+
+    let receipt = TransactionReceipt {
+        block_number: block_number.as_u64(),
+        gas_used: gas_used.as_u64(),
+        effective_gas_price: effective_gas_price.map(|price| price.as_u128()),
+    };
+    Ok(Some((status, receipt)))
+}
+
+fn parse_fee_history(result: Value) -> Result<(U256, U256), String> {
+    // chains without EIP-1559 leave the base fee out
+    if result["baseFeePerGas"].is_null() {
+        return Ok((U256::zero(), U256::zero()));
+    }
+    let base_fee = result["baseFeePerGas"]
+        .as_array()
+        .and_then(|fees| fees.last())
+        .ok_or_else(|| "Invalid fee history".to_string())
+        .and_then(parse_quantity)?;
+
+    let mut rewards = result["reward"]
+        .as_array()
+        .ok_or_else(|| "Invalid fee history".to_string())?
+        .iter()
+        .map(|reward| parse_quantity(&reward[0]))
+        .collect::<Result<Vec<U256>, String>>()?;
+    rewards.sort();
+    let priority_fee = rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+
+    Ok((base_fee, priority_fee))
+}
+
+fn apply_multiplier(value: U256, percent: u64) -> Result<U256, String> {
+    value
+        .checked_mul(U256::from(percent))
+        .map(|scaled| scaled / 100)
+        .ok_or_else(|| "Fee overflow".to_string())
+}
+
+/// Parses a hex encoded JSON-RPC quantity such as `"0x1a"`.
+pub fn parse_quantity(value: &Value) -> Result<U256, String> {
+    let hex = value
+        .as_str()
+        .and_then(|s| s.strip_prefix("0x"))
+        .filter(|s| !s.is_empty() && s.len() <= 64)
+        .ok_or_else(|| format!("Invalid quantity {}", value))?;
+
+    U256::from_str_radix(hex, 16).map_err(|_| format!("Invalid quantity {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mocks::StubTransport;
+    use futures::executor::block_on;
+
+    #[test]
+    fn response_bytes_limit_per_method() {
+        let transport = HttpOutcallTransport::default();
+        let receipt = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getTransactionReceipt", "params": [] });
+        let nonce = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getTransactionCount", "params": [] });
+
+        let fee_history = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_feeHistory", "params": [] });
+
+        assert_eq!(transport.response_bytes_limit(receipt.to_string().as_bytes()), 8_192);
+        assert_eq!(transport.response_bytes_limit(fee_history.to_string().as_bytes()), 64 * 1024);
+        assert_eq!(transport.response_bytes_limit(nonce.to_string().as_bytes()), 8_192);
+        assert_eq!(transport.response_bytes_limit(b"not json"), 8_192);
+    }
+
+    #[test]
+    fn send_raw_transaction_valid() {
+        let transport = StubTransport::default().with_result("eth_sendRawTransaction", json!("0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let hash = block_on(client.send_raw_transaction(&[0x02, 0xf8])).unwrap();
+        assert_eq!(hash.to_string(), "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b");
+
+        let requests = transport.requests.borrow();
+        assert_eq!(requests[0].0, "https://rpc.example.com");
+        assert_eq!(requests[0].1["method"], "eth_sendRawTransaction");
+        assert_eq!(requests[0].1["params"], json!(["0x02f8"]));
+    }
+
+    #[test]
+    fn send_raw_transaction_already_known() {
+        let signed_tx = [0x02, 0xf8];
+        let expected = H256::try_from(&get_transaction_hash(&signed_tx)[..]).unwrap();
+        let messages = [
+            "already known",
+            "nonce too low: address 0x01, tx: 0 state: 1",
+            "Transaction with the same hash was already imported.",
+        ];
+        for message in messages {
+            let transport = StubTransport::default().with_error("eth_sendRawTransaction", message);
+            let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+            assert_eq!(block_on(client.send_raw_transaction(&signed_tx)), Ok(expected));
+        }
+
+        let transport = StubTransport::default().with_error("eth_sendRawTransaction", "insufficient funds");
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+        let result = block_on(client.send_raw_transaction(&signed_tx));
+        assert_eq!(result, Err("RPC error insufficient funds".to_string()));
+    }
+
+    #[test]
+    fn call_with_rpc_error() {
+        let expected = Err("RPC error nonce too low".to_string());
+        let transport = StubTransport::default().with_error("eth_sendRawTransaction", "nonce too low");
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let result = block_on(client.call("eth_sendRawTransaction", json!([])));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn call_with_invalid_response() {
+        let transport = StubTransport::default().with_raw_response("eth_chainId", "<html></html>");
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let result = block_on(client.call("eth_chainId", json!([])));
+        assert!(result.unwrap_err().starts_with("Invalid RPC response"));
+    }
+
+    #[test]
+    fn get_transaction_count_valid() {
+        let transport = StubTransport::default().with_result("eth_getTransactionCount", json!("0x1a"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let address = address();
+        let count = block_on(client.get_transaction_count(&address, "pending")).unwrap();
+        assert_eq!(count, 26);
+
+        let requests = transport.requests.borrow();
+        assert_eq!(requests[0].1["params"], json!([address.to_string(), "pending"]));
+    }
+
+    #[test]
+    fn estimate_fees_from_fee_history() {
+        let transport = StubTransport::default()
+            .with_result(
+                "eth_feeHistory",
+                json!({
+                    "oldestBlock": "0x10",
+                    "baseFeePerGas": ["0x64", "0x6e", "0x78", "0x82"],
+                    "gasUsedRatio": [0.5, 0.5, 0.5],
+                    "reward": [["0x5"], ["0x1"], ["0x3"]],
+                }),
+            )
+            .with_result("eth_estimateGas", json!("0x5208"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let call = transaction_call(&address(), None, &[0x60], U256::zero());
+        let fees = block_on(client.estimate_fees(&FeeEstimationConfig::default(), call)).unwrap();
+
+        assert_eq!(fees.max_priority_fee_per_gas, U256::from(3));
+        assert_eq!(fees.max_fee_per_gas, U256::from(130 * 2 + 3));
+        assert_eq!(fees.gas_limit, 25_200);
+        assert!(!fees.legacy);
+
+        let requests = transport.requests.borrow();
+        assert_eq!(requests[0].1["params"], json!(["0xa", "latest", [50]]));
+        assert_eq!(requests[1].1["params"][0]["data"], "0x60");
+        assert!(requests[1].1["params"][0].get("to").is_none());
+    }
+
+    #[test]
+    fn estimate_fees_without_fee_history() {
+        let transport = StubTransport::default()
+            .with_error_code("eth_feeHistory", -32601, "the method eth_feeHistory does not exist")
+            .with_result("eth_gasPrice", json!("0x3b9aca00"))
+            .with_result("eth_estimateGas", json!("0x5208"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let call = transaction_call(&address(), None, &[], U256::zero());
+        let config = FeeEstimationConfig {
+            gas_limit_multiplier_percent: 100,
+            ..Default::default()
+        };
+        let fees = block_on(client.estimate_fees(&config, call)).unwrap();
+
+        assert_eq!(fees.max_priority_fee_per_gas, U256::from(1_000_000_000));
+        assert_eq!(fees.max_fee_per_gas, U256::from(1_000_000_000));
+        assert_eq!(fees.gas_limit, 21_000);
+        assert!(fees.legacy);
+    }
+
+    #[test]
+    fn estimate_fees_without_base_fee() {
+        let transport = StubTransport::default()
+            .with_result("eth_feeHistory", json!({ "oldestBlock": "0x10", "reward": [] }))
+            .with_result("eth_gasPrice", json!("0x3b9aca00"))
+            .with_result("eth_estimateGas", json!("0x5208"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let call = transaction_call(&address(), None, &[], U256::zero());
+        let fees = block_on(client.estimate_fees(&FeeEstimationConfig::default(), call)).unwrap();
+
+        assert_eq!(fees.max_priority_fee_per_gas, U256::from(1_000_000_000));
+        assert_eq!(fees.max_fee_per_gas, U256::from(1_000_000_000));
+        assert!(fees.legacy);
+    }
+
+    #[test]
+    fn estimate_fees_fee_history_error() {
+        let transport = StubTransport::default()
+            .with_error("eth_feeHistory", "rate limited")
+            .with_result("eth_gasPrice", json!("0x3b9aca00"))
+            .with_result("eth_estimateGas", json!("0x5208"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let call = transaction_call(&address(), None, &[], U256::zero());
+        let result = block_on(client.estimate_fees(&FeeEstimationConfig::default(), call));
+
+        assert_eq!(result.map(|fees| fees.gas_limit), Err("RPC error rate limited".to_string()));
+        assert_eq!(transport.requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn get_transaction_receipt_valid() {
+        let transport = StubTransport::default().with_result(
+            "eth_getTransactionReceipt",
+            json!({
+                "blockNumber": "0x10",
+                "status": "0x0",
+                "gasUsed": "0x5208",
+                "effectiveGasPrice": "0x3b9aca00",
+            }),
+        );
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let (status, receipt) = block_on(client.get_transaction_receipt(&hash())).unwrap().unwrap();
+        assert_eq!(status, TransactionStatus::Reverted);
+        assert_eq!(receipt.block_number, 16);
+        assert_eq!(receipt.gas_used, 21_000);
+        assert_eq!(receipt.effective_gas_price, Some(1_000_000_000));
+    }
+
+    #[test]
+    fn get_transaction_receipt_without_effective_gas_price() {
+        let transport = StubTransport::default().with_result(
+            "eth_getTransactionReceipt",
+            json!({ "blockNumber": "0x10", "status": "0x1", "gasUsed": "0x5208" }),
+        );
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let (status, receipt) = block_on(client.get_transaction_receipt(&hash())).unwrap().unwrap();
+        assert_eq!(status, TransactionStatus::Success);
+        assert_eq!(receipt.effective_gas_price, None);
+    }
+
+    #[test]
+    fn receipt_response_limit_per_chain() {
+        let transport = StubTransport::default()
+            .with_result("eth_getTransactionReceipt", Value::Null)
+            .with_result("eth_getTransactionCount", json!("0x1"));
+        let chain_config = ChainConfig {
+            rpc_providers: vec!["https://rpc.example.com".to_string()],
+            receipt_max_response_bytes: Some(1024 * 1024),
+            ..Default::default()
+        };
+        let client = RpcClient::for_chain(&transport, &chain_config).unwrap();
+
+        block_on(client.get_transaction_receipt(&hash())).unwrap();
+        block_on(client.get_transaction_count(&address(), "latest")).unwrap();
+        assert_eq!(*transport.response_limits.borrow(), vec![1024 * 1024]);
+    }
+
+    #[test]
+    fn get_transaction_receipt_pending() {
+        let transport = StubTransport::default().with_result("eth_getTransactionReceipt", Value::Null);
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let receipt = block_on(client.get_transaction_receipt(&hash())).unwrap();
+        assert_eq!(receipt, None);
+    }
+
+    fn address() -> Address {
+        "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap()
+    }
+
+    fn hash() -> H256 {
+        H256([0x12; 32])
+    }
+
+    fn providers() -> Vec<String> {
+        vec![
+            "https://a.example.com".to_string(),
+            "https://b.example.com".to_string(),
+            "https://c.example.com".to_string(),
+        ]
+    }
+
+    #[test]
+    fn consensus_tolerates_one_faulty_provider() {
+        let transport = StubTransport::default()
+            .with_result("eth_getTransactionCount", json!("0x5"))
+            .with_provider_result("https://b.example.com", "eth_getTransactionCount", json!("0x9"));
+        let client = RpcClient::with_providers(&transport, &providers(), 2).unwrap();
+
+        let count = block_on(client.get_transaction_count(&address(), "pending")).unwrap();
+        assert_eq!(count, 5);
+        assert_eq!(transport.requests.borrow().len(), 3);
+    }
+
+    #[test]
+    fn consensus_disagreement() {
+        let transport = StubTransport::default()
+            .with_result("eth_getTransactionCount", json!("0x5"))
+            .with_provider_result("https://b.example.com", "eth_getTransactionCount", json!("0x9"));
+        let client = RpcClient::with_providers(&transport, &providers(), 3).unwrap();
+
+        let result = block_on(client.get_transaction_count(&address(), "pending"));
+        assert_eq!(result, Err("RPC providers disagree on eth_getTransactionCount".to_string()));
+    }
+
+    #[test]
+    fn consensus_rejects_two_groups_reaching_threshold() {
+        let mut providers = providers();
+        providers.push("https://d.example.com".to_string());
+        let transport = StubTransport::default()
+            .with_result("eth_getTransactionCount", json!("0x5"))
+            .with_provider_result("https://c.example.com", "eth_getTransactionCount", json!("0x9"))
+            .with_provider_result("https://d.example.com", "eth_getTransactionCount", json!("0x9"));
+        let client = RpcClient::with_providers(&transport, &providers, 2).unwrap();
+
+        let result = block_on(client.get_transaction_count(&address(), "pending"));
+        assert_eq!(result, Err("RPC providers disagree on eth_getTransactionCount".to_string()));
+    }
+
+    #[test]
+    fn consensus_compares_parsed_receipts() {
+        let receipt = json!({
+            "status": "0x1",
+            "blockNumber": "0x10",
+            "gasUsed": "0x5208",
+            "effectiveGasPrice": "0x3b9aca00",
+        });
+        let mut with_logs = receipt.clone();
+        with_logs["logs"] = json!([]);
+        let transport = StubTransport::default()
+            .with_result("eth_getTransactionReceipt", receipt)
+            .with_provider_result("https://c.example.com", "eth_getTransactionReceipt", with_logs);
+        let client = RpcClient::with_providers(&transport, &providers(), 3).unwrap();
+
+        let (status, receipt) = block_on(client.get_transaction_receipt(&hash())).unwrap().unwrap();
+        assert_eq!(status, TransactionStatus::Success);
+        assert_eq!(receipt.block_number, 16);
+    }
+
+    #[test]
+    fn consensus_not_enough_responses() {
+        let transport = StubTransport::default()
+            .with_error("eth_gasPrice", "rate limited")
+            .with_provider_result("https://a.example.com", "eth_gasPrice", json!("0x1"));
+        let client = RpcClient::with_providers(&transport, &providers(), 2).unwrap();
+
+        let result = block_on(client.gas_price());
+        assert_eq!(result, Err("RPC error rate limited".to_string()));
+    }
+
+    #[test]
+    fn with_providers_invalid_threshold() {
+        let transport = StubTransport::default();
+        let result = RpcClient::with_providers(&transport, &providers(), 0).map(|_| ());
+        assert_eq!(result, Err("RPC threshold must be between 1 and 3".to_string()));
+    }
+
+    #[test]
+    fn erc_20_balance_of_valid() {
+        let balance = "0x00000000000000000000000000000000000000000000000000000000000003e8";
+        let transport = StubTransport::default().with_result("eth_call", json!(balance));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let owner = address();
+        let contract: Address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap();
+        let result = block_on(client.erc_20_balance_of(&contract, &owner)).unwrap();
+        assert_eq!(result, U256::from(1000));
+
+        let requests = transport.requests.borrow();
+        assert_eq!(
+            requests[0].1["params"],
+            json!([
+                {
+                    "to": contract.to_string(),
+                    "data": "0x70a08231000000000000000000000000907dc4d0be5d691970cae886fcab34ed65a2cd66",
+                },
+                "latest"
+            ])
+        );
+    }
+
+    #[test]
+    fn erc_20_balance_of_not_a_contract() {
+        let transport = StubTransport::default().with_result("eth_call", json!("0x"));
+        let client = RpcClient::new(&transport, "https://rpc.example.com");
+
+        let owner = address();
+        let result = block_on(client.erc_20_balance_of(&owner, &owner));
+        assert_eq!(result, Err("Invalid balanceOf result".to_string()));
+    }
+
+    #[test]
+    fn parse_quantity_invalid() {
+        assert_eq!(parse_quantity(&json!("0x")), Err("Invalid quantity \"0x\"".to_string()));
+        assert_eq!(parse_quantity(&json!("12")), Err("Invalid quantity \"12\"".to_string()));
+        assert_eq!(parse_quantity(&json!(12)), Err("Invalid quantity 12".to_string()));
+        assert_eq!(parse_quantity(&json!("0x0")), Ok(U256::zero()));
+    }
+
+    #[test]
+    fn transform_drops_headers() {
+        let response = HttpResponse {
+            status: candid::Nat::from(200),
+            headers: vec![HttpHeader {
+                name: "Date".to_string(),
+                value: "Mon, 01 Jan 2024 00:00:00 GMT".to_string(),
+            }],
+            body: vec![1, 2, 3],
+        };
+        let transformed = transform(response);
+        assert!(transformed.headers.is_empty());
+        assert_eq!(transformed.body, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn transform_normalises_known_transaction_errors() {
+        let response = |message: &str| HttpResponse {
+            status: candid::Nat::from(200),
+            headers: vec![],
+            body: json!({ "jsonrpc": "2.0", "id": 7, "error": { "code": -32003, "message": message } })
+                .to_string()
+                .into_bytes(),
+        };
+
+        let known = transform(response("already known")).body;
+        assert_eq!(transform(response("nonce too low: next nonce 5, tx nonce 4")).body, known);
+        let error: Value = serde_json::from_slice(&known).unwrap();
+        assert_eq!(error["error"]["message"], ALREADY_KNOWN);
+
+        let other = response("insufficient funds");
+        assert_eq!(transform(other.clone()).body, other.body);
+    }
+}
@@ -0,0 +1,125 @@
+use ic_cdk::api::stable::StableMemoryError;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// System API of the canister the library runs in: time, stable memory and
+/// the cycles attached to the current message.
+///
+/// The default runtime calls the IC. Downstream canisters can register the
+/// `testing::MockRuntime` of the `testing` feature in their unit tests instead.
+pub trait Runtime {
+    /// Current time in nanoseconds since the epoch.
+    fn time(&self) -> u64;
+
+    /// Size of stable memory in WASM pages.
+    fn stable64_size(&self) -> u64;
+
+    /// Grows stable memory by `new_pages` and returns the previous size.
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError>;
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]);
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]);
+
+    fn msg_cycles_available(&self) -> u64;
+
+    /// Accepts up to `max_amount` of the cycles attached to the message and
+    /// returns the amount accepted.
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64;
+
+    /// Cycles refunded by the last inter-canister call.
+    fn msg_cycles_refunded(&self) -> u64;
+}
+
+/// Runtime backed by the system API of the IC.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanisterRuntime;
+
+impl Runtime for CanisterRuntime {
+    fn time(&self) -> u64 {
+        ic_cdk::api::time()
+    }
+
+    fn stable64_size(&self) -> u64 {
+        ic_cdk::api::stable::stable64_size()
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        ic_cdk::api::stable::stable64_grow(new_pages)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        ic_cdk::api::stable::stable64_read(offset, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        ic_cdk::api::stable::stable64_write(offset, buf)
+    }
+
+    fn msg_cycles_available(&self) -> u64 {
+        ic_cdk::api::call::msg_cycles_available()
+    }
+
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        ic_cdk::api::call::msg_cycles_accept(max_amount)
+    }
+
+    fn msg_cycles_refunded(&self) -> u64 {
+        ic_cdk::api::call::msg_cycles_refunded()
+    }
+}
+
+thread_local! {
+    static RUNTIME: RefCell<Rc<dyn Runtime>> = RefCell::new(default_runtime());
+}
+
+#[cfg(not(test))]
+fn default_runtime() -> Rc<dyn Runtime> {
+    Rc::new(CanisterRuntime)
+}
+
+#[cfg(test)]
+fn default_runtime() -> Rc<dyn Runtime> {
+    Rc::new(crate::mocks::TestRuntime)
+}
+
+/// Sets the runtime of the current thread. It is not persisted across upgrades.
+pub fn set_runtime(runtime: Rc<dyn Runtime>) {
+    RUNTIME.with(|r| *r.borrow_mut() = runtime);
+}
+
+pub fn get_runtime() -> Rc<dyn Runtime> {
+    RUNTIME.with(|r| r.borrow().clone())
+}
+
+pub(crate) fn time() -> u64 {
+    get_runtime().time()
+}
+
+pub(crate) fn stable64_size() -> u64 {
+    get_runtime().stable64_size()
+}
+
+pub(crate) fn stable64_grow(new_pages: u64) -> Result<u64, StableMemoryError> {
+    get_runtime().stable64_grow(new_pages)
+}
+
+pub(crate) fn stable64_read(offset: u64, buf: &mut [u8]) {
+    get_runtime().stable64_read(offset, buf)
+}
+
+pub(crate) fn stable64_write(offset: u64, buf: &[u8]) {
+    get_runtime().stable64_write(offset, buf)
+}
+
+pub(crate) fn msg_cycles_available() -> u64 {
+    get_runtime().msg_cycles_available()
+}
+
+pub(crate) fn msg_cycles_accept(max_amount: u64) -> u64 {
+    get_runtime().msg_cycles_accept(max_amount)
+}
+
+pub(crate) fn msg_cycles_refunded() -> u64 {
+    get_runtime().msg_cycles_refunded()
+}
@@ -0,0 +1,207 @@
+use crate::derivation::{derive_public_key, derive_secret_key};
+use crate::ecdsa::reply::{ECDSAPublicKeyResponse, SignWithECDSAResponse};
+use crate::ecdsa::request::{ECDSAPublicKey, EcdsaCurve, EcdsaKeyId, SignWithECDSA};
+use crate::{ic_call, msg_cycles_refunded};
+use crate::state::{ExtendedPublicKey, STATE};
+use easy_hasher::easy_hasher;
+use ic_cdk::export::Principal;
+use libsecp256k1::{Message, PublicKey, SecretKey};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+
+pub type SignerFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, String>> + 'a>>;
+
+/// Source of the secp256k1 keys behind user addresses.
+///
+/// The default signer calls the management canister. Downstream canisters can
+/// register a [`LocalSigner`] in their unit tests instead.
+pub trait Signer {
+    /// Returns the compressed public key and chain code for `derivation_path`.
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> SignerFuture<'_, ExtendedPublicKey>;
+
+    /// Signs a 32-byte digest and returns the 64-byte `r || s` signature.
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> SignerFuture<'_, Vec<u8>>;
+
+    /// Same as [`Signer::sign_digest`], also returning the cycles the signature
+    /// cost. Signers that do not pay for signatures cost nothing.
+    fn sign_digest_with_cost(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: Vec<u8>,
+    ) -> SignerFuture<'_, (Vec<u8>, u64)> {
+        let signature = self.sign_digest(derivation_path, message_hash);
+        Box::pin(async move { Ok((signature.await?, 0)) })
+    }
+}
+
+thread_local! {
+    static SIGNER: RefCell<Rc<dyn Signer>> = RefCell::new(Rc::new(ManagementCanisterSigner));
+}
+
+/// Sets the signer used to create addresses and sign transactions. It is not
+/// persisted across upgrades. The cached root key is dropped, since it may
+/// belong to the previous signer.
+pub fn set_signer(signer: Rc<dyn Signer>) {
+    SIGNER.with(|s| *s.borrow_mut() = signer);
+    STATE.with(|s| s.borrow_mut().root_key = None);
+}
+
+pub fn get_signer() -> Rc<dyn Signer> {
+    SIGNER.with(|s| s.borrow().clone())
+}
+
+/// Signer backed by the threshold ECDSA API of the management canister. The
+/// key name and the cycles attached to signing come from the state config,
+/// and the cost of a signature excludes the cycles refunded by the call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManagementCanisterSigner;
+
+impl ManagementCanisterSigner {
+    fn key_id() -> EcdsaKeyId {
+        EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: STATE.with(|s| s.borrow().config.key_name.clone()),
+        }
+    }
+}
+
+impl Signer for ManagementCanisterSigner {
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> SignerFuture<'_, ExtendedPublicKey> {
+        let request = ECDSAPublicKey {
+            canister_id: None,
+            derivation_path,
+            key_id: Self::key_id(),
+        };
+
+        Box::pin(async move {
+            let (res,): (ECDSAPublicKeyResponse,) =
+                ic_call(Principal::management_canister(), "ecdsa_public_key", (request,), 0)
+                    .await
+                    .map_err(|e| format!("Failed to call ecdsa_public_key {}", e.1))?;
+
+            Ok(ExtendedPublicKey {
+                public_key: res.public_key,
+                chain_code: res.chain_code,
+            })
+        })
+    }
+
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> SignerFuture<'_, Vec<u8>> {
+        let signature = self.sign_digest_with_cost(derivation_path, message_hash);
+        Box::pin(async move { signature.await.map(|(signature, _)| signature) })
+    }
+
+    fn sign_digest_with_cost(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: Vec<u8>,
+    ) -> SignerFuture<'_, (Vec<u8>, u64)> {
+        let request = SignWithECDSA {
+            message_hash,
+            derivation_path,
+            key_id: Self::key_id(),
+        };
+        let cycles = STATE.with(|s| s.borrow().config.sign_cycles);
+
+        Box::pin(async move {
+            let (res,): (SignWithECDSAResponse,) =
+                ic_call(Principal::management_canister(), "sign_with_ecdsa", (request,), cycles)
+                    .await
+                    .map_err(|e| format!("Failed to call sign_with_ecdsa {}", e.1))?;
+
+            Ok((res.signature, cycles.saturating_sub(msg_cycles_refunded())))
+        })
+    }
+}
+
+/// In-memory signer holding a root secret key. Keys of derivation paths are
+/// derived like the IC derives them, so different principals get different
+/// addresses.
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    secret_key: SecretKey,
+    chain_code: Vec<u8>,
+}
+
+impl LocalSigner {
+    /// Uses the hash of the private key as chain code.
+    pub fn new(private_key: &[u8]) -> Result<Self, String> {
+        let chain_code = easy_hasher::raw_keccak256(private_key.to_vec()).to_vec();
+        LocalSigner::with_chain_code(private_key, chain_code)
+    }
+
+    pub fn with_chain_code(private_key: &[u8], chain_code: Vec<u8>) -> Result<Self, String> {
+        let secret_key = SecretKey::parse_slice(private_key).map_err(|_| "Invalid private key".to_string())?;
+        if chain_code.len() != 32 {
+            return Err("Invalid chain code".to_string());
+        }
+        Ok(LocalSigner { secret_key, chain_code })
+    }
+}
+
+impl Signer for LocalSigner {
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> SignerFuture<'_, ExtendedPublicKey> {
+        let root = ExtendedPublicKey {
+            public_key: PublicKey::from_secret_key(&self.secret_key).serialize_compressed().to_vec(),
+            chain_code: self.chain_code.clone(),
+        };
+        let result = derive_public_key(&root, &derivation_path);
+        Box::pin(async move { result })
+    }
+
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> SignerFuture<'_, Vec<u8>> {
+        let result = derive_secret_key(&self.secret_key, &self.chain_code, &derivation_path).and_then(|secret_key| {
+            let message = Message::parse_slice(&message_hash).map_err(|_| "Invalid message hash".to_string())?;
+            let (signature, _) = libsecp256k1::sign(&message, &secret_key);
+            Ok(signature.serialize().to_vec())
+        });
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::string_to_vec_u8;
+    use futures::executor::block_on;
+
+    const PRIVATE_KEY: &str = "5c86d3784f39013aa50aada6d97f9bad733636d57bf6bb18b0bca1ffcff374b4";
+
+    #[test]
+    fn local_signer_derives_keys_per_path() {
+        let signer = LocalSigner::new(&string_to_vec_u8(PRIVATE_KEY)).unwrap();
+
+        let root = block_on(signer.public_key(vec![])).unwrap();
+        let first = block_on(signer.public_key(vec![vec![1]])).unwrap();
+        let second = block_on(signer.public_key(vec![vec![2]])).unwrap();
+
+        assert_eq!(root.public_key.len(), 33);
+        assert_ne!(root, first);
+        assert_ne!(first, second);
+        assert_eq!(first, block_on(signer.public_key(vec![vec![1]])).unwrap());
+    }
+
+    #[test]
+    fn local_signer_signature_verifies() {
+        let signer = LocalSigner::new(&string_to_vec_u8(PRIVATE_KEY)).unwrap();
+        let path = vec![vec![1, 2, 3]];
+        let message_hash = easy_hasher::raw_keccak256(b"hello".to_vec()).to_vec();
+
+        let signature = block_on(signer.sign_digest(path.clone(), message_hash.clone())).unwrap();
+        let public_key = block_on(signer.public_key(path)).unwrap();
+
+        let signature = libsecp256k1::Signature::parse_standard_slice(&signature).unwrap();
+        let public_key = PublicKey::parse_slice(&public_key.public_key, None).unwrap();
+        let message = Message::parse_slice(&message_hash).unwrap();
+        assert!(libsecp256k1::verify(&message, &signature, &public_key));
+    }
+
+    #[test]
+    fn local_signer_invalid_message_hash() {
+        let signer = LocalSigner::new(&string_to_vec_u8(PRIVATE_KEY)).unwrap();
+        let result = block_on(signer.sign_digest(vec![], vec![0; 31]));
+        assert_eq!(result, Err("Invalid message hash".to_string()));
+    }
+}
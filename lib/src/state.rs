@@ -6,17 +6,42 @@ use ic_cdk::export::{
 use std::cell::RefCell;
 use std::collections::HashMap;
 
+use crate::nonce::NonceManager;
+
+#[derive(CandidType, Serialize, Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+pub enum TransactionStatus {
+    #[default]
+    Pending,
+    Success,
+    Reverted,
+    Dropped,
+}
+
+#[derive(CandidType, Serialize, Debug, Clone, Deserialize, PartialEq)]
+pub struct TransactionReceipt {
+    pub block_number: u64,
+    pub gas_used: u64,
+    /// Missing from receipts of nodes that predate EIP-1559.
+    pub effective_gas_price: Option<u128>,
+}
+
 #[derive(CandidType, Serialize, Debug, Clone, Deserialize)]
 pub struct Transaction {
     pub data: Vec<u8>,
+    pub hash: Vec<u8>,
     pub timestamp: u64,
+    pub status: TransactionStatus,
+    pub receipt: Option<TransactionReceipt>,
 }
 
 impl Default for Transaction {
     fn default() -> Self {
         Transaction {
             data: vec![],
+            hash: vec![],
             timestamp: u64::from(0 as u64),
+            status: TransactionStatus::Pending,
+            receipt: None,
         }
     }
 }
@@ -24,6 +49,7 @@ impl Default for Transaction {
 #[derive(Debug, Clone, CandidType, Serialize, Deserialize)]
 pub struct TransactionChainData {
     pub nonce: u64,
+    pub nonce_manager: NonceManager,
     pub transactions: Vec<Transaction>,
 }
 
@@ -31,14 +57,26 @@ impl Default for TransactionChainData {
     fn default() -> Self {
         TransactionChainData {
             nonce: 0 as u64,
+            nonce_manager: NonceManager::default(),
             transactions: vec![],
         }
     }
 }
+/// Public key together with the chain code needed to derive keys below it.
+#[derive(Clone, Debug, CandidType, Deserialize, Default, PartialEq)]
+pub struct ExtendedPublicKey {
+    pub public_key: Vec<u8>,
+    pub chain_code: Vec<u8>,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Default)]
 pub struct UserData {
     pub public_key: Vec<u8>,
     pub transactions: HashMap<u64, TransactionChainData>,
+    /// Set by the user to allow `sign_hash` on their key.
+    pub raw_signing_enabled: bool,
+    /// Cycles deposited by the user to pay for their signatures.
+    pub cycles_balance: u64,
 }
 
 
@@ -86,10 +124,71 @@ impl From<Environment> for Config {
      }
 }
 
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub struct FeeEstimationConfig {
+    /// Number of recent blocks sampled with `eth_feeHistory`.
+    pub fee_history_blocks: u64,
+    /// Reward percentile used for the priority fee.
+    pub priority_fee_percentile: u8,
+    /// Headroom applied to the next block base fee, 200 means twice the base fee.
+    pub base_fee_multiplier_percent: u64,
+    /// Headroom applied to the `eth_estimateGas` result.
+    pub gas_limit_multiplier_percent: u64,
+}
+
+impl Default for FeeEstimationConfig {
+    fn default() -> Self {
+        FeeEstimationConfig {
+            fee_history_blocks: 10,
+            priority_fee_percentile: 50,
+            base_fee_multiplier_percent: 200,
+            gas_limit_multiplier_percent: 120,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainConfig {
+    /// RPC endpoints queried for every request.
+    pub rpc_providers: Vec<String>,
+    /// Number of providers that must return the same result for it to be accepted.
+    pub rpc_threshold: u64,
+    pub auto_sync_nonce: bool,
+    pub fee_estimation: FeeEstimationConfig,
+    /// Pending transactions without a receipt after this many nanoseconds are
+    /// marked as dropped and their nonce is given back. Zero keeps them pending
+    /// and polled forever.
+    pub receipt_timeout_ns: u64,
+    /// Response size limit of `eth_getTransactionReceipt` on this chain, for
+    /// chains whose receipts carry many logs. `None` keeps the limit of the
+    /// transport.
+    pub receipt_max_response_bytes: Option<u64>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        ChainConfig {
+            rpc_providers: vec![],
+            rpc_threshold: 1,
+            auto_sync_nonce: false,
+            fee_estimation: FeeEstimationConfig::default(),
+            receipt_timeout_ns: 3 * 60 * 60 * 1_000_000_000,
+            receipt_max_response_bytes: None,
+        }
+    }
+}
+
 #[derive(Default, CandidType, Deserialize, Debug, Clone)]
 pub struct State {
     pub users: HashMap<Principal, UserData>,
-    pub config: Config
+    pub config: Config,
+    pub chains: HashMap<u64, ChainConfig>,
+    pub controllers: Vec<Principal>,
+    /// Key of the canister returned by `ecdsa_public_key` with an empty
+    /// derivation path. User keys are derived from it locally.
+    pub root_key: Option<ExtendedPublicKey>,
+    /// Allows users that opted in to call `sign_hash`.
+    pub raw_signing_enabled: bool,
 }
 
 thread_local! {
@@ -0,0 +1,264 @@
+use crate::runtime::{set_runtime, Runtime};
+use crate::signer::{set_signer, LocalSigner, Signer, SignerFuture};
+use crate::state::{ExtendedPublicKey, STATE};
+use easy_hasher::easy_hasher;
+use ic_cdk::api::stable::StableMemoryError;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// In-memory stand-in for the system API, so that the library runs outside a
+/// canister. Time only moves when set, and cycles are attached to the current
+/// message with [`attach_cycles`].
+///
+/// [`attach_cycles`]: MockRuntime::attach_cycles
+#[derive(Debug, Default)]
+pub struct MockRuntime {
+    time: Cell<u64>,
+    stable_memory: RefCell<Vec<u8>>,
+    msg_cycles: Cell<u64>,
+    cycles_refunded: Cell<u64>,
+}
+
+impl MockRuntime {
+    /// Registers this runtime for the current thread.
+    pub fn install(self) -> Rc<Self> {
+        let runtime = Rc::new(self);
+        set_runtime(runtime.clone());
+        runtime
+    }
+
+    pub fn set_time(&self, time: u64) {
+        self.time.set(time);
+    }
+
+    pub fn advance_time(&self, nanos: u64) {
+        self.time.set(self.time.get() + nanos);
+    }
+
+    /// Attaches cycles to the current message, for `deposit_cycles`.
+    pub fn attach_cycles(&self, amount: u64) {
+        self.msg_cycles.set(amount);
+    }
+
+    /// Sets the cycles refunded by every call.
+    pub fn refund_cycles(&self, amount: u64) {
+        self.cycles_refunded.set(amount);
+    }
+}
+
+impl Runtime for MockRuntime {
+    fn time(&self) -> u64 {
+        self.time.get()
+    }
+
+    fn stable64_size(&self) -> u64 {
+        self.stable_memory.borrow().len() as u64 / WASM_PAGE_SIZE
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        let previous = self.stable64_size();
+        let size = (previous + new_pages) * WASM_PAGE_SIZE;
+        self.stable_memory.borrow_mut().resize(size as usize, 0);
+        Ok(previous)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.stable_memory.borrow()[offset..offset + buf.len()]);
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        let offset = offset as usize;
+        self.stable_memory.borrow_mut()[offset..offset + buf.len()].copy_from_slice(buf);
+    }
+
+    fn msg_cycles_available(&self) -> u64 {
+        self.msg_cycles.get()
+    }
+
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        let accepted = max_amount.min(self.msg_cycles.get());
+        self.msg_cycles.set(self.msg_cycles.get() - accepted);
+        accepted
+    }
+
+    fn msg_cycles_refunded(&self) -> u64 {
+        self.cycles_refunded.get()
+    }
+}
+
+/// Call received by [`MockManagementCanister`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagementCall {
+    pub method: String,
+    pub key_name: String,
+    pub derivation_path: Vec<Vec<u8>>,
+    /// Set for `sign_with_ecdsa` only.
+    pub message_hash: Option<Vec<u8>>,
+    pub cycles: u64,
+}
+
+/// Stand-in for the threshold ECDSA API of the management canister, for unit
+/// tests of canisters built on this crate.
+///
+/// Keys are derived deterministically from a seed, with a distinct key per
+/// derivation path. Every call is recorded together with the cycles the real
+/// signer would attach, and calls can be made to fail with [`reject_calls`].
+/// Installing it also installs a [`MockRuntime`], reachable with [`runtime`],
+/// so that the whole library runs outside a canister.
+///
+/// [`reject_calls`]: MockManagementCanister::reject_calls
+/// [`runtime`]: MockManagementCanister::runtime
+pub struct MockManagementCanister {
+    signer: LocalSigner,
+    runtime: Rc<MockRuntime>,
+    rejection: RefCell<Option<String>>,
+    calls: RefCell<Vec<ManagementCall>>,
+}
+
+impl Default for MockManagementCanister {
+    fn default() -> Self {
+        MockManagementCanister::new(b"ic-evm-sign")
+    }
+}
+
+impl MockManagementCanister {
+    pub fn new(seed: &[u8]) -> Self {
+        let root_key = easy_hasher::raw_keccak256(seed.to_vec()).to_vec();
+        MockManagementCanister {
+            signer: LocalSigner::new(&root_key).expect("seed does not give a valid key"),
+            runtime: Rc::new(MockRuntime::default()),
+            rejection: RefCell::new(None),
+            calls: RefCell::new(vec![]),
+        }
+    }
+
+    /// Registers this mock as the signer, and its runtime as the runtime, of
+    /// the current thread.
+    pub fn install(self) -> Rc<Self> {
+        let mock = Rc::new(self);
+        set_signer(mock.clone());
+        set_runtime(mock.runtime.clone());
+        mock
+    }
+
+    pub fn runtime(&self) -> Rc<MockRuntime> {
+        self.runtime.clone()
+    }
+
+    /// Makes every following call fail with `message`.
+    pub fn reject_calls(&self, message: &str) {
+        *self.rejection.borrow_mut() = Some(message.to_string());
+    }
+
+    pub fn accept_calls(&self) {
+        *self.rejection.borrow_mut() = None;
+    }
+
+    pub fn calls(&self) -> Vec<ManagementCall> {
+        self.calls.borrow().clone()
+    }
+
+    pub fn cycles_sent(&self) -> u64 {
+        self.calls.borrow().iter().map(|call| call.cycles).sum()
+    }
+
+    fn record(&self, method: &str, derivation_path: &[Vec<u8>], message_hash: Option<Vec<u8>>) -> Result<(), String> {
+        let (key_name, sign_cycles) = STATE.with(|s| {
+            let config = &s.borrow().config;
+            (config.key_name.clone(), config.sign_cycles)
+        });
+        let cycles = if message_hash.is_some() { sign_cycles } else { 0 };
+
+        self.calls.borrow_mut().push(ManagementCall {
+            method: method.to_string(),
+            key_name,
+            derivation_path: derivation_path.to_vec(),
+            message_hash,
+            cycles,
+        });
+
+        match self.rejection.borrow().as_ref() {
+            Some(message) => Err(format!("Failed to call {} {}", method, message)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Signer for MockManagementCanister {
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> SignerFuture<'_, ExtendedPublicKey> {
+        match self.record("ecdsa_public_key", &derivation_path, None) {
+            Ok(()) => self.signer.public_key(derivation_path),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> SignerFuture<'_, Vec<u8>> {
+        match self.record("sign_with_ecdsa", &derivation_path, Some(message_hash.clone())) {
+            Ok(()) => self.signer.sign_digest(derivation_path, message_hash),
+            Err(e) => Box::pin(async move { Err(e) }),
+        }
+    }
+
+    fn sign_digest_with_cost(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: Vec<u8>,
+    ) -> SignerFuture<'_, (Vec<u8>, u64)> {
+        let signature = self.sign_digest(derivation_path, message_hash);
+        let cycles = self.calls.borrow().last().map_or(0, |call| call.cycles);
+        Box::pin(async move { Ok((signature.await?, cycles)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn keys_are_deterministic_per_path() {
+        let first = MockManagementCanister::default();
+        let second = MockManagementCanister::default();
+
+        let key = block_on(first.public_key(vec![vec![1]])).unwrap();
+        assert_eq!(key, block_on(second.public_key(vec![vec![1]])).unwrap());
+        assert_ne!(key, block_on(first.public_key(vec![vec![2]])).unwrap());
+        assert_ne!(key, block_on(MockManagementCanister::new(b"other").public_key(vec![vec![1]])).unwrap());
+    }
+
+    #[test]
+    fn records_calls_and_cycles() {
+        crate::init(Some(crate::state::Environment::Staging));
+        let mock = MockManagementCanister::default();
+        block_on(mock.public_key(vec![vec![1]])).unwrap();
+        block_on(mock.sign_digest(vec![vec![1]], vec![0; 32])).unwrap();
+
+        let calls = mock.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].method, "ecdsa_public_key");
+        assert_eq!(calls[0].cycles, 0);
+        assert_eq!(calls[1].method, "sign_with_ecdsa");
+        assert_eq!(calls[1].message_hash, Some(vec![0; 32]));
+        assert_eq!(calls[1].key_name, "test_key_1");
+        assert_eq!(mock.cycles_sent(), 10_000_000_000);
+
+        let (_, cost) = block_on(mock.sign_digest_with_cost(vec![vec![1]], vec![0; 32])).unwrap();
+        assert_eq!(cost, 10_000_000_000);
+    }
+
+    #[test]
+    fn rejects_calls() {
+        let mock = MockManagementCanister::default();
+        mock.reject_calls("out of cycles");
+
+        let result = block_on(mock.sign_digest(vec![], vec![0; 32]));
+        assert_eq!(result, Err("Failed to call sign_with_ecdsa out of cycles".to_string()));
+        assert_eq!(mock.calls().len(), 1);
+
+        mock.accept_calls();
+        assert!(block_on(mock.sign_digest(vec![], vec![0; 32])).is_ok());
+    }
+}
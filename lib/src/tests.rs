@@ -9,30 +9,28 @@ fn create_new_user() {
     let principal_id = Principal::from_text("aaaaa-aa").unwrap();
 
     let res = block_on(create_address(principal_id)).unwrap();
-    assert_eq!(res.address.len(), 42);
+    assert_eq!(res.address.to_string().len(), 42);
 }
 
 #[test]
 fn sign_legacy_transaction() {
     let expected_get_signature_before = Err("This is not a signed transaction".to_string());
-    let expected_get_signature_after ="c9e2682ec5084986365523c4268c5956c064c1ee85dc208364cb71e93edabab612ffab0eaed3e34865b225e9f349945599f8641cd806dc43029e0f92fdca23cb";
+    let expected_get_signature_after ="f871e17d66ab1bcfbebe783567acb51df0d51492f0973832110933a21eb583323518e0c8f687b4138c63bbd03ebb27796af8b7dd9657dc68059bdab30d90d5ee";
     let expected_get_recovery_id_before = Err("This is not a signed transaction".to_string());
-    let expected_get_recovery_id_after = 0;
+    let expected_get_recovery_id_after = 1;
     let expected_get_message_to_sign_after = "eb86127620fbc047c6b6c2fcedea010143538e452dc7cb67a7fb1f8a00abdbd9";
-    let expected_address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66";
+    let expected_address = "0x80d6cae8b397e4a6e578c79d628af5cff8e13507";
 
     use primitive_types::U256;
     let tx = transaction::TransactionLegacy {
         nonce: 0,
         gas_price: U256::zero(),
         gas_limit: 0,
-        to: "0x0000000000000000000000000000000000000000".to_string(),
+        to: Some(Address::default()),
         value: U256::zero(),
-        data: "0x00".to_string(),
+        data: Bytes(vec![0x00]),
         chain_id: 1,
-        v: "0x00".to_string(),
-        r: "0x00".to_string(),
-        s: "0x00".to_string(),
+        ..Default::default()
     };
     assert_eq!(tx.is_signed(), false);
 
@@ -58,19 +56,19 @@ fn sign_legacy_transaction() {
     let recovery_id = tx_signed.get_recovery_id().unwrap();
     assert_eq!(recovery_id, expected_get_recovery_id_after);
 
-    let address = recover_address(signature, recovery_id, msg).unwrap();
-    assert_eq!(address, expected_address);
+    let address = verify::recover_address(&msg, &signature, recovery_id).unwrap();
+    assert_eq!(address.to_string(), expected_address);
 
     assert_eq!(res_create.address, address)
 }
 #[test]
 fn sign_eip2930_transaction() {
     let expected_get_signature_before = Err("This is not a signed transaction".to_string());
-    let expected_get_signature_after ="31cf08411809b04f8a82d2b07d6c33f7aa46d805e833f832464fd237c00a11d35104f49a601cf90fd5fe6297ec403959b7f649b5125ea3bcde084e9893fee5c6";
+    let expected_get_signature_after ="69aa1080f84fd68abba1ae5a3b9bbec3d92695a3ec8ff9d8f83f9bd108c147b82f810938e49deedc36ca9922d9352b53429e31fe33dd3c91e4f9f27224a51472";
     let expected_get_recovery_id_before = Err("This is not a signed transaction".to_string());
-    let expected_get_recovery_id_after = 1;
+    let expected_get_recovery_id_after = 0;
     let expected_get_message_to_sign_after = "1db9b0174e2b28a2073c88acbc792a5445407c5a8bf7bc5c65a047d45885eb89";
-    let expected_address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66";
+    let expected_address = "0x80d6cae8b397e4a6e578c79d628af5cff8e13507";
 
     use primitive_types::U256;
     let tx = transaction::Transaction2930 {
@@ -78,13 +76,11 @@ fn sign_eip2930_transaction() {
         nonce: 0,
         gas_price: U256::zero(),
         gas_limit: 0,
-        to: "0x0000000000000000000000000000000000000000".to_string(),
+        to: Some(Address::default()),
         value: U256::zero(),
-        data: "0x00".to_string(),
+        data: Bytes(vec![0x00]),
         access_list: vec![],
-        v: "0x00".to_string(),
-        r: "0x00".to_string(),
-        s: "0x00".to_string(),
+        ..Default::default()
     };
 
     assert_eq!(tx.is_signed(), false);
@@ -109,8 +105,8 @@ fn sign_eip2930_transaction() {
     let recovery_id = tx_signed.get_recovery_id().unwrap();
     assert_eq!(recovery_id, expected_get_recovery_id_after);
 
-    let address = recover_address(signature, recovery_id, message).unwrap();
-    assert_eq!(address, expected_address);
+    let address = verify::recover_address(&message, &signature, recovery_id).unwrap();
+    assert_eq!(address.to_string(), expected_address);
 
     assert_eq!(res_create.address, address)
 }
@@ -118,11 +114,11 @@ fn sign_eip2930_transaction() {
 #[test]
 fn sign_eip1559_transaction() {
     let expected_get_signature_before = Err("This is not a signed transaction".to_string());
-    let expected_get_signature_after ="29edd4e1d65e1b778b464112d2febc6e97bb677aba5034408fd27b49921beca94c4e5b904d58553bcd9c788360e0bd55c513922cf1f33a6386033e886cd4f77f";
+    let expected_get_signature_after ="45d38cb248e3b784d91800c076de82d88d58eee904799c2af2e42129e35c2e2225494c9d422df69f84c4cc952e152834e58519da621c496795dd6dde9a61f409";
     let expected_get_recovery_id_before = Err("This is not a signed transaction".to_string());
     let expected_get_recovery_id_after = 0;
     let expected_get_message_to_sign_after = "79965df63d7d9364f4bc8ed54ffd1c267042d4db673e129e3c459afbcb73a6f1";
-    let expected_address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66";
+    let expected_address = "0x80d6cae8b397e4a6e578c79d628af5cff8e13507";
 
     use primitive_types::U256;
     let tx = transaction::Transaction1559 {
@@ -131,13 +127,11 @@ fn sign_eip1559_transaction() {
         max_priority_fee_per_gas: U256::zero(),
         gas_limit: 0,
         max_fee_per_gas: U256::zero(),
-        to: "0x0000000000000000000000000000000000000000".to_string(),
+        to: Some(Address::default()),
         value: U256::zero(),
-        data: "0x00".to_string(),
+        data: Bytes(vec![0x00]),
         access_list: vec![],
-        v: "0x00".to_string(),
-        r: "0x00".to_string(),
-        s: "0x00".to_string(),
+        ..Default::default()
     };
     assert_eq!(tx.is_signed(), false);
 
@@ -164,22 +158,22 @@ fn sign_eip1559_transaction() {
     let recovery_id = tx_signed.get_recovery_id().unwrap();
     assert_eq!(recovery_id, expected_get_recovery_id_after);
 
-    let address = recover_address(signature, recovery_id, message).unwrap();
-    assert_eq!(address, expected_address);
+    let address = verify::recover_address(&message, &signature, recovery_id).unwrap();
+    assert_eq!(address.to_string(), expected_address);
 
     assert_eq!(res_create.address, address)
 }
 
 #[test]
 fn recover_address_valid() {
-    let expected = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66";
+    let expected = "0x80d6cae8b397e4a6e578c79d628af5cff8e13507";
 
-    let signature =string_to_vec_u8("29edd4e1d65e1b778b464112d2febc6e97bb677aba5034408fd27b49921beca94c4e5b904d58553bcd9c788360e0bd55c513922cf1f33a6386033e886cd4f77f");
+    let signature =string_to_vec_u8("45d38cb248e3b784d91800c076de82d88d58eee904799c2af2e42129e35c2e2225494c9d422df69f84c4cc952e152834e58519da621c496795dd6dde9a61f409");
     let recovery_id = 0;
     let message = string_to_vec_u8("79965df63d7d9364f4bc8ed54ffd1c267042d4db673e129e3c459afbcb73a6f1");
-    let address = recover_address(signature, recovery_id, message).unwrap();
+    let address = verify::recover_address(&message, &signature, recovery_id).unwrap();
 
-    assert_eq!(address, expected);
+    assert_eq!(address.to_string(), expected);
 }
 
 #[test]
@@ -189,7 +183,7 @@ fn recover_address_with_invalid_signature() {
     let signature = string_to_vec_u8("");
     let recovery_id = 0;
     let message = string_to_vec_u8("79965df63d7d9364f4bc8ed54ffd1c267042d4db673e129e3c459afbcb73a6f1");
-    let result = recover_address(signature, recovery_id, message);
+    let result = verify::recover_address(&message, &signature, recovery_id);
 
     assert_eq!(result, expected);
 }
@@ -198,37 +192,1680 @@ fn recover_address_with_invalid_signature() {
 fn recover_address_with_invalid_message() {
     let expected = Err("Invalid message".to_string());
 
-    let signature = string_to_vec_u8("29edd4e1d65e1b778b464112d2febc6e97bb677aba5034408fd27b49921beca94c4e5b904d58553bcd9c788360e0bd55c513922cf1f33a6386033e886cd4f77f");
+    let signature = string_to_vec_u8("45d38cb248e3b784d91800c076de82d88d58eee904799c2af2e42129e35c2e2225494c9d422df69f84c4cc952e152834e58519da621c496795dd6dde9a61f409");
     let recovery_id = 0;
     let message = string_to_vec_u8("");
-    let result = recover_address(signature, recovery_id, message);
+    let result = verify::recover_address(&message, &signature, recovery_id);
 
     assert_eq!(result, expected);
 }
 
-fn recover_address(
-    signature: Vec<u8>,
-    recovery_id: u8,
-    message: Vec<u8>,
-) -> Result<String, String> {
-    if signature.len() != 64 {
-        return Err("Invalid signature".to_string());
+#[test]
+fn recover_transaction_signer_all_types() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let address = block_on(create_address(principal_id)).unwrap().address;
+
+    let legacy = block_on(sign_transaction(unsigned_legacy_transaction(0), 1, principal_id)).unwrap();
+    let eip1559 = block_on(sign_transaction(unsigned_eip1559_transaction(1), 1, principal_id)).unwrap();
+
+    assert_eq!(verify::recover_transaction_signer(&legacy.sign_tx), Ok(address));
+    assert_eq!(verify::recover_transaction_signer(&eip1559.sign_tx), Ok(address));
+}
+
+#[test]
+fn verify_transaction_by_principal() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let res = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+
+    assert_eq!(verify::verify_transaction(principal_id, &res.sign_tx), Ok(true));
+    assert_eq!(verify::verify_transaction(other, &res.sign_tx), Ok(false));
+    assert_eq!(
+        verify::verify_transaction(principal_id, &unsigned_eip1559_transaction(0)),
+        Err("This is not a signed transaction".to_string())
+    );
+}
+
+fn unsigned_legacy_transaction(nonce: u64) -> Vec<u8> {
+    use primitive_types::U256;
+    let tx = transaction::TransactionLegacy {
+        nonce,
+        gas_price: U256::zero(),
+        gas_limit: 0,
+        to: Some(Address::default()),
+        value: U256::zero(),
+        data: Bytes(vec![0x00]),
+        chain_id: 1,
+        ..Default::default()
+    };
+    tx.serialize().unwrap()
+}
+
+#[test]
+fn sign_transaction_chain_id_mismatch() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+
+    let result = block_on(sign_transaction(unsigned_eip1559_transaction(0), 5, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("chain id 1 does not match 5".to_string()));
+
+    // unsigned legacy transactions may carry the chain id in v, as in the EIP-155 payload
+    let tx = transaction::TransactionLegacy {
+        v: 5,
+        ..transaction::TransactionLegacy::try_from((unsigned_legacy_transaction(0), 1)).unwrap()
+    };
+    let raw_tx = tx.serialize().unwrap();
+    let result = block_on(sign_transaction(raw_tx.clone(), 1, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("chain id 5 does not match 1".to_string()));
+
+    let res = block_on(sign_transaction(raw_tx, 5, principal_id)).unwrap();
+    assert_eq!(transaction::get_chain_id(&res.sign_tx), Ok(Some(5)));
+    assert_eq!(verify::verify_transaction(principal_id, &res.sign_tx), Ok(true));
+
+    assert!(get_caller_data(principal_id, 1).unwrap().transactions.transactions.is_empty());
+}
+
+#[test]
+fn sign_transaction_pre_eip155() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let address = block_on(create_address(principal_id)).unwrap().address;
+    let options = SignOptions {
+        pre_eip155: true,
+        ..Default::default()
+    };
+
+    let result = block_on(sign_transaction(unsigned_legacy_transaction(0), 0, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("chain id 0 requires pre-EIP-155 signing".to_string()));
+
+    let result = block_on(sign_transaction_with_options(
+        unsigned_eip1559_transaction(0),
+        1,
+        principal_id,
+        options.clone(),
+    ));
+    assert_eq!(
+        result.map(|r| r.sign_tx),
+        Err("only legacy transactions can be signed without EIP-155".to_string())
+    );
+
+    let tx = transaction::TransactionLegacy {
+        gas_price: U256::from(1_000),
+        gas_limit: 21_000,
+        to: Some(Address::default()),
+        ..Default::default()
+    };
+    let res = block_on(sign_transaction_with_options(tx.serialize().unwrap(), 1, principal_id, options)).unwrap();
+
+    let signed = transaction::TransactionLegacy::try_from((res.sign_tx.clone(), 1)).unwrap();
+    assert!(signed.v == 27 || signed.v == 28);
+    assert_eq!(signed.chain_id, 0);
+    assert_eq!(transaction::get_chain_id(&res.sign_tx), Ok(None));
+
+    let recovered = verify::recover_address(
+        &signed.get_message_to_sign().unwrap(),
+        &signed.get_signature().unwrap(),
+        signed.get_recovery_id().unwrap(),
+    );
+    assert_eq!(recovered, Ok(address));
+
+    // replacements keep the signing scheme of the original transaction
+    let res = block_on(replace_transaction(principal_id, 1, TransactionRef::Nonce(0), None)).unwrap();
+    let replaced = transaction::TransactionLegacy::try_from((res.tx, 1)).unwrap();
+    assert!(replaced.v == 27 || replaced.v == 28);
+    assert_eq!(replaced.gas_price, U256::from(1_100));
+}
+
+#[test]
+fn sign_already_signed_transaction() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let signed = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+
+    let result = block_on(sign_transaction(signed.sign_tx.clone(), 1, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("transaction is already signed".to_string()));
+
+    let requests = vec![SignTransactionRequest { hex_raw_tx: signed.sign_tx, chain_id: 1, assign_nonce: false }];
+    let results = block_on(sign_transactions_batch(requests, principal_id));
+    assert_eq!(results[0].as_ref().err(), Some(&"transaction is already signed".to_string()));
+
+    assert_eq!(get_caller_data(principal_id, 1).unwrap().transactions.transactions.len(), 1);
+}
+
+#[test]
+fn sign_already_signed_transaction_with_verification() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    let signed = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+    let options = SignOptions {
+        verify_signed: true,
+        ..Default::default()
+    };
+
+    let res = block_on(sign_transaction_with_options(signed.sign_tx.clone(), 1, principal_id, options.clone())).unwrap();
+    assert_eq!(res.sign_tx, signed.sign_tx);
+    let chain_data = get_caller_data(principal_id, 1).unwrap().transactions;
+    assert_eq!(chain_data.transactions.len(), 1);
+    assert_eq!(chain_data.nonce, 1);
+
+    let expected = format!(
+        "transaction is signed by 0x80d6cae8b397e4a6e578c79d628af5cff8e13507, expected {}",
+        get_address(other).unwrap()
+    );
+    let result = block_on(sign_transaction_with_options(signed.sign_tx, 1, other, options.clone()));
+    assert_eq!(result.map(|r| r.sign_tx), Err(expected));
+
+    let tx = transaction::TransactionLegacy {
+        nonce: 1,
+        gas_limit: 21_000,
+        to: Some(Address::default()),
+        ..Default::default()
+    };
+    let pre_eip155 = SignOptions {
+        pre_eip155: true,
+        ..Default::default()
+    };
+    let signed = block_on(sign_transaction_with_options(tx.serialize().unwrap(), 1, principal_id, pre_eip155)).unwrap();
+    let address = get_address(principal_id).unwrap();
+    assert_eq!(verify::recover_transaction_signer_with_options(&signed.sign_tx, true), Ok(address));
+
+    let result = block_on(sign_transaction_with_options(signed.sign_tx.clone(), 1, principal_id, options));
+    assert_eq!(result.map(|r| r.sign_tx), Err("transaction is not EIP-155 protected".to_string()));
+
+    let options = SignOptions {
+        pre_eip155: true,
+        verify_signed: true,
+    };
+    let res = block_on(sign_transaction_with_options(signed.sign_tx.clone(), 1, principal_id, options)).unwrap();
+    assert_eq!(res.sign_tx, signed.sign_tx);
+}
+
+#[test]
+fn sign_hash_requires_opt_in() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let message_hash = vec![7; 32];
+
+    let result = block_on(sign_hash(principal_id, message_hash.clone()));
+    assert_eq!(result.map(|r| r.v), Err("raw hash signing is disabled".to_string()));
+
+    set_raw_signing(principal_id, true).unwrap();
+    let result = block_on(sign_hash(principal_id, message_hash.clone()));
+    assert_eq!(result.map(|r| r.v), Err("raw hash signing is not enabled for this user".to_string()));
+
+    block_on(set_user_raw_signing(principal_id, true)).unwrap();
+    let result = block_on(sign_hash(principal_id, vec![7; 31]));
+    assert_eq!(result.map(|r| r.v), Err("message hash must be 32 bytes".to_string()));
+
+    set_raw_signing(principal_id, false).unwrap();
+    let result = block_on(sign_hash(principal_id, message_hash));
+    assert_eq!(result.map(|r| r.v), Err("raw hash signing is disabled".to_string()));
+
+    let entries = audit::read_entries(0, 100).unwrap();
+    let sign_hash_entries = entries
+        .iter()
+        .filter(|e| e.operation == AuditOperation::SignHash)
+        .collect::<Vec<_>>();
+    assert_eq!(sign_hash_entries.len(), 4);
+    assert!(sign_hash_entries.iter().all(|e| e.outcome.is_err() && e.cycles == 0));
+}
+
+#[test]
+fn sign_hash_valid() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let message_hash = vec![7; 32];
+    set_raw_signing(principal_id, true).unwrap();
+    block_on(set_user_raw_signing(principal_id, true)).unwrap();
+
+    let res = block_on(sign_hash(principal_id, message_hash.clone())).unwrap();
+    assert!(res.v == 27 || res.v == 28);
+
+    let signature = [res.r.as_bytes(), res.s.as_bytes()].concat();
+    let address = verify::recover_address(&message_hash, &signature, res.v - 27);
+    assert_eq!(address, get_address(principal_id));
+
+    let entry = audit::read_entries(0, 100).unwrap().pop().unwrap();
+    assert_eq!(entry.caller, principal_id);
+    assert_eq!(entry.operation, AuditOperation::SignHash);
+    assert_eq!(entry.hash, Some(message_hash));
+    assert_eq!(entry.account, get_address(principal_id).ok());
+    assert_eq!(entry.outcome, Ok(()));
+}
+
+#[test]
+fn sign_transaction_insufficient_cycles() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    STATE.with(|s| s.borrow_mut().config.sign_cycles = 100);
+
+    let result = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("insufficient cycles balance: 0 available, 100 required".to_string()));
+
+    // the nonce is released for the next attempt
+    mocks::attach_cycles(150);
+    assert_eq!(block_on(deposit_cycles(principal_id)), Ok(150));
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    assert_eq!(get_cycles_balance(principal_id), 50);
+}
+
+#[test]
+fn sign_transaction_debits_real_cost() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    STATE.with(|s| s.borrow_mut().config.sign_cycles = 100);
+    block_on(credit_cycles(principal_id, 250)).unwrap();
+    mocks::refund_cycles(30);
+
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+    assert_eq!(get_cycles_balance(principal_id), 180);
+
+    set_raw_signing(principal_id, true).unwrap();
+    block_on(set_user_raw_signing(principal_id, true)).unwrap();
+    block_on(sign_hash(principal_id, vec![7; 32])).unwrap();
+    assert_eq!(get_cycles_balance(principal_id), 110);
+
+    let entries = audit::read_entries(0, 100).unwrap();
+    let costs = entries
+        .iter()
+        .filter(|e| matches!(e.operation, AuditOperation::SignTransaction | AuditOperation::SignHash))
+        .map(|e| e.cycles)
+        .collect::<Vec<_>>();
+    assert_eq!(costs, vec![70, 70]);
+}
+
+#[test]
+fn sign_transactions_batch_cannot_overdraw() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    STATE.with(|s| s.borrow_mut().config.sign_cycles = 100);
+    block_on(credit_cycles(principal_id, 150)).unwrap();
+
+    let requests = (0..2)
+        .map(|nonce| SignTransactionRequest {
+            hex_raw_tx: unsigned_eip1559_transaction(nonce),
+            chain_id: 1,
+            assign_nonce: false,
+        })
+        .collect();
+    let results = block_on(sign_transactions_batch(requests, principal_id));
+
+    assert!(results[0].is_ok());
+    assert_eq!(
+        results[1].as_ref().err().unwrap(),
+        "insufficient cycles balance: 50 available, 100 required"
+    );
+    assert_eq!(get_cycles_balance(principal_id), 50);
+}
+
+#[test]
+fn deposit_cycles_credits_balance() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    assert_eq!(get_cycles_balance(principal_id), 0);
+
+    mocks::attach_cycles(500);
+    assert_eq!(block_on(deposit_cycles(principal_id)), Ok(500));
+    assert_eq!(block_on(deposit_cycles(principal_id)), Ok(500));
+    assert_eq!(block_on(credit_cycles(principal_id, u64::MAX)), Err("cycles balance overflow".to_string()));
+
+    // cycles that cannot be credited are not accepted, so they go back to the caller
+    mocks::attach_cycles(u64::MAX);
+    assert_eq!(block_on(deposit_cycles(principal_id)), Err("cycles balance overflow".to_string()));
+    assert_eq!(mocks::msg_cycles_available(), u64::MAX);
+    mocks::attach_cycles(0);
+
+    let entries = get_audit_log(principal_id, 1, 10).unwrap().entries;
+    let operations = entries.iter().map(|e| e.operation.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        operations,
+        vec![
+            AuditOperation::DepositCycles { amount: 500 },
+            AuditOperation::DepositCycles { amount: 0 },
+            AuditOperation::DepositCycles { amount: u64::MAX },
+            AuditOperation::DepositCycles { amount: u64::MAX },
+        ]
+    );
+    assert!(entries[2].outcome.is_err());
+    assert!(entries[3].outcome.is_err());
+}
+
+#[test]
+fn audit_log_records_signing() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    init_controller(principal_id);
+
+    let res = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    let result = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id));
+    assert!(result.is_err());
+    block_on(sign_transaction_with_options(
+        res.sign_tx.clone(),
+        chain_id,
+        principal_id,
+        SignOptions { verify_signed: true, ..Default::default() },
+    ))
+    .unwrap();
+
+    let page = get_audit_log(principal_id, 1, 10).unwrap();
+    assert_eq!(page.total, 4);
+    let [signed, failed, verified] = &page.entries[..] else {
+        panic!("expected 3 entries");
+    };
+
+    assert_eq!(signed.operation, AuditOperation::SignTransaction);
+    assert_eq!(signed.account, get_address(principal_id).ok());
+    assert_eq!(signed.chain_id, Some(chain_id));
+    assert_eq!(signed.hash, Some(utils::get_transaction_hash(&res.sign_tx)));
+    assert_eq!(signed.cycles, STATE.with(|s| s.borrow().config.sign_cycles));
+    assert_eq!(signed.outcome, Ok(()));
+
+    assert_eq!(failed.operation, AuditOperation::SignTransaction);
+    assert_eq!(failed.hash, None);
+    assert_eq!(failed.cycles, 0);
+    assert_eq!(failed.outcome, Err("nonce 0 is already in use".to_string()));
+
+    assert_eq!(verified.operation, AuditOperation::VerifyTransaction);
+    assert_eq!(verified.cycles, 0);
+}
+
+#[test]
+fn audit_log_records_admin_actions() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    set_raw_signing(principal_id, true).unwrap();
+    assert!(set_chain_config(principal_id, 5, ChainConfig::default()).is_err());
+
+    let page = get_audit_log(principal_id, 0, 10).unwrap();
+    let operations = page.entries.iter().map(|e| e.operation.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        operations,
+        vec![
+            AuditOperation::AddController { principal: principal_id },
+            AuditOperation::SetRawSigning { enabled: true },
+            AuditOperation::SetChainConfig,
+        ]
+    );
+    assert_eq!(page.entries[2].chain_id, Some(5));
+    assert!(page.entries[2].outcome.is_err());
+
+    let csv = export_audit_log(principal_id, 1, 1).unwrap();
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv.lines().nth(1).unwrap().contains("SetRawSigning { enabled: true }"));
+}
+
+#[test]
+fn audit_log_records_added_controller_and_caller() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    init_controller(principal_id);
+    add_controller(principal_id, other).unwrap();
+
+    let entry = &get_audit_log(principal_id, 1, 1).unwrap().entries[0];
+    assert_eq!(entry.caller, principal_id);
+    assert_eq!(entry.operation, AuditOperation::AddController { principal: other });
+}
+
+#[test]
+fn audit_log_records_nonce_actions() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    init_controller(principal_id);
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(1), chain_id, principal_id)).unwrap();
+
+    reserve_nonce(principal_id, chain_id).unwrap();
+    release_nonce(principal_id, chain_id, 1).unwrap();
+    mark_nonce_broadcast(principal_id, chain_id, 0).unwrap();
+    assert!(release_nonce(principal_id, chain_id, 0).is_err());
+    confirm_nonce(principal_id, chain_id, 0).unwrap();
+    resync_nonce(principal_id, chain_id, 3).unwrap();
+
+    let entries = get_audit_log(principal_id, 3, 10).unwrap().entries;
+    let operations = entries.iter().map(|e| e.operation.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        operations,
+        vec![
+            AuditOperation::ReserveNonce { nonce: Some(2) },
+            AuditOperation::ReleaseNonce { nonce: 1 },
+            AuditOperation::MarkNonceBroadcast { nonce: 0 },
+            AuditOperation::ReleaseNonce { nonce: 0 },
+            AuditOperation::ConfirmNonce { nonce: 0 },
+            AuditOperation::ResyncNonce { transaction_count: 3 },
+        ]
+    );
+    assert!(entries.iter().all(|e| e.chain_id == Some(chain_id)));
+    assert_eq!(entries[3].outcome, Err("nonce 0 was already broadcast".to_string()));
+
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    assert!(reserve_nonce(other, chain_id).is_err());
+    let entry = get_audit_log(principal_id, 9, 1).unwrap().entries.remove(0);
+    assert_eq!(entry.operation, AuditOperation::ReserveNonce { nonce: None });
+    assert_eq!(entry.outcome, Err("this user does not exist".to_string()));
+}
+
+// Stable memory that can't grow any further.
+struct FullStableMemory;
+
+impl runtime::Runtime for FullStableMemory {
+    fn time(&self) -> u64 {
+        mocks::TestRuntime.time()
+    }
+
+    fn stable64_size(&self) -> u64 {
+        mocks::TestRuntime.stable64_size()
+    }
+
+    fn stable64_grow(&self, _new_pages: u64) -> Result<u64, ic_cdk::api::stable::StableMemoryError> {
+        Err(ic_cdk::api::stable::StableMemoryError::OutOfMemory)
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        mocks::TestRuntime.stable64_read(offset, buf)
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        mocks::TestRuntime.stable64_write(offset, buf)
+    }
+
+    fn msg_cycles_available(&self) -> u64 {
+        mocks::TestRuntime.msg_cycles_available()
+    }
+
+    fn msg_cycles_accept(&self, max_amount: u64) -> u64 {
+        mocks::TestRuntime.msg_cycles_accept(max_amount)
     }
-    if message.len() != 32 {
-        return Err("Invalid message".to_string());
+
+    fn msg_cycles_refunded(&self) -> u64 {
+        mocks::TestRuntime.msg_cycles_refunded()
     }
+}
+
+#[test]
+fn full_stable_memory_drops_audit_entries() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    runtime::set_runtime(std::rc::Rc::new(FullStableMemory));
+    init_controller(principal_id);
+    let res = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id));
+    runtime::set_runtime(std::rc::Rc::new(mocks::TestRuntime));
+
+    assert!(res.is_ok());
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.transactions.len(), 1);
+    let log = get_audit_log(principal_id, 0, 10).unwrap();
+    assert_eq!((log.total, log.dropped), (0, 2));
+}
+
+#[test]
+fn admin_actions_require_controller() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    init_controller(principal_id);
+
+    let expected = Err("caller is not a controller".to_string());
+    assert_eq!(add_controller(other, other), expected);
+    assert_eq!(set_raw_signing(other, true), expected);
+    let config = ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() };
+    assert_eq!(set_chain_config(other, 1, config.clone()), expected);
+    assert!(!is_controller(other));
+    assert!(get_chain_config(1).is_err());
+
+    add_controller(principal_id, other).unwrap();
+    assert_eq!(set_chain_config(other, 1, config), Ok(()));
+}
+
+#[test]
+fn add_controller_without_controllers() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+
+    assert_eq!(add_controller(other, other), Err("caller is not a controller".to_string()));
+    assert!(!is_controller(other));
+
+    init_controller(principal_id);
+    init_controller(principal_id);
+    assert!(is_controller(principal_id));
+    let log = get_audit_log(principal_id, 0, 10).unwrap();
+    assert_eq!(log.total, 2);
+    assert_eq!(log.entries[1].operation, AuditOperation::AddController { principal: principal_id });
+}
+
+#[test]
+fn audit_log_requires_controller() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+
+    let expected = "caller is not a controller".to_string();
+    assert_eq!(get_audit_log(principal_id, 0, 10).map(|p| p.total), Err(expected.clone()));
+    assert_eq!(export_audit_log(principal_id, 0, 10), Err(expected));
+}
+
+#[test]
+fn upgrade_keeps_state_and_audit_log() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    init_controller(principal_id);
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+
+    pre_upgrade();
+    STATE.with(|s| *s.borrow_mut() = State::default());
+    post_upgrade();
+
+    assert!(get_caller_data(principal_id, chain_id).is_some());
+    assert_eq!(get_audit_log(principal_id, 0, 10).unwrap().total, 2);
+
+    // new entries overwrite the saved state
+    block_on(sign_transaction(unsigned_eip1559_transaction(1), chain_id, principal_id)).unwrap();
+    pre_upgrade();
+    post_upgrade();
+    assert_eq!(get_audit_log(principal_id, 0, 10).unwrap().total, 3);
+}
+
+#[test]
+fn upgrade_from_state_without_audit_log() {
+    use crate::migration::{ConfigV0, StateV0, TransactionChainDataV0, TransactionV0, UserDataV0};
+    use std::collections::HashMap;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+    let public_key = STATE.with(|s| s.borrow().users[&principal_id].public_key.clone());
+    let tx = unsigned_eip1559_transaction(1);
+
+    // layout written by `stable_save` in the first release
+    let baseline = StateV0 {
+        users: HashMap::from([(
+            principal_id,
+            UserDataV0 {
+                public_key,
+                transactions: HashMap::from([(
+                    1,
+                    TransactionChainDataV0 {
+                        nonce: 2,
+                        transactions: vec![TransactionV0 { data: tx.clone(), timestamp: 7 }],
+                    },
+                )]),
+            },
+        )]),
+        config: ConfigV0 {
+            env: Environment::Staging,
+            key_name: "test_key_1".to_string(),
+            sign_cycles: 10,
+        },
+    };
+    let state = candid::encode_args((&baseline,)).unwrap();
+    mocks::stable64_grow(1).unwrap();
+    mocks::stable64_write(0, &state);
+    STATE.with(|s| *s.borrow_mut() = State::default());
+
+    post_upgrade();
+
+    assert!(get_address(principal_id).is_ok());
+    assert_eq!(STATE.with(|s| s.borrow().config.sign_cycles), 10);
+    let user = get_caller_data(principal_id, 1).unwrap();
+    assert_eq!(user.transactions.nonce_manager.next_nonce(), 2);
+    assert_eq!(user.transactions.transactions[0].data, tx);
 
-    let signature_bytes: [u8; 64] = signature[..].try_into().unwrap();
-    let signature_bytes_64 = libsecp256k1::Signature::parse_standard(&signature_bytes).unwrap();
+    init_controller(principal_id);
+    assert_eq!(get_audit_log(principal_id, 0, 10).unwrap().total, 1);
+}
+
+#[test]
+fn sign_transaction_with_used_nonce() {
+    let expected = Err("nonce 0 is already in use".to_string());
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    block_on(sign_transaction(unsigned_legacy_transaction(0), chain_id, principal_id)).unwrap();
+    let result = block_on(sign_transaction(unsigned_legacy_transaction(0), chain_id, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), expected);
+
+    let result = block_on(sign_transaction(unsigned_legacy_transaction(2), chain_id, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("nonce 2 leaves a gap, expected 1".to_string()));
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce, 1);
+}
+
+#[test]
+fn reserve_and_release_nonce() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    assert_eq!(reserve_nonce(principal_id, chain_id), Ok(0));
+    assert_eq!(reserve_nonce(principal_id, chain_id), Ok(1));
+
+    block_on(sign_transaction(unsigned_legacy_transaction(1), chain_id, principal_id)).unwrap();
+    release_nonce(principal_id, chain_id, 0).unwrap();
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce, 0);
+
+    mark_nonce_broadcast(principal_id, chain_id, 1).unwrap();
+    assert_eq!(
+        release_nonce(principal_id, chain_id, 1),
+        Err("nonce 1 was already broadcast".to_string())
+    );
+
+    resync_nonce(principal_id, chain_id, 2).unwrap();
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce, 2);
+}
+
+#[test]
+fn sign_transaction_with_local_signer() {
+    use crate::signer::{set_signer, LocalSigner};
+    use std::rc::Rc;
+
+    let signer = LocalSigner::new(&[7; 32]).unwrap();
+    set_signer(Rc::new(signer));
+
+    let first = Principal::from_text("aaaaa-aa").unwrap();
+    let second = Principal::from_text("2vxsx-fae").unwrap();
+    let res_first = block_on(create_address(first)).unwrap();
+    let res_second = block_on(create_address(second)).unwrap();
+    assert_ne!(res_first.address, res_second.address);
+
+    let res = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, first)).unwrap();
+    let tx = transaction::get_transaction(&res.sign_tx, 1).unwrap();
+    assert!(tx.is_signed());
+}
+
+#[test]
+fn sign_transaction_with_mock_management_canister() {
+    use crate::testing::MockManagementCanister;
+
+    let mock = MockManagementCanister::default().install();
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    mock.reject_calls("canister out of cycles");
+    let result = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id));
+    assert_eq!(
+        result.map(|r| r.sign_tx),
+        Err("Failed to call sign_with_ecdsa canister out of cycles".to_string())
+    );
+
+    mock.accept_calls();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
 
-    let recovery_id_byte = libsecp256k1::RecoveryId::parse(u8::try_from(recovery_id).unwrap()).unwrap();
+    let calls = mock.calls();
+    assert_eq!(calls.len(), 3);
+    assert!(calls[0].derivation_path.is_empty());
+    assert!(calls[1..].iter().all(|call| call.derivation_path == vec![get_derivation_path(principal_id)]));
+}
+
+#[test]
+fn create_address_caches_root_key() {
+    use crate::signer::Signer;
+    use crate::testing::MockManagementCanister;
+
+    let mock = MockManagementCanister::default().install();
+    let first = Principal::from_text("aaaaa-aa").unwrap();
+    let second = Principal::from_text("2vxsx-fae").unwrap();
+    let res_first = block_on(create_address(first)).unwrap();
+    block_on(create_address(second)).unwrap();
+
+    let calls = mock.calls();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].method, "ecdsa_public_key");
+
+    // The locally derived key matches the key the canister would return for the path.
+    let key = block_on(mock.public_key(vec![get_derivation_path(first)])).unwrap();
+    assert_eq!(get_address_from_public_key(key.public_key).unwrap(), res_first.address);
+}
+
+#[test]
+fn sign_transaction_without_create_address() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    assert!(get_caller_data(principal_id, 1).is_none());
+
+    let res = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+    let tx = transaction::get_transaction(&res.sign_tx, 1).unwrap();
+    assert!(tx.is_signed());
+
+    let user = get_caller_data(principal_id, 1).unwrap();
+    assert_eq!(user.address.to_string(), "0x80d6cae8b397e4a6e578c79d628af5cff8e13507");
+    assert_eq!(user.transactions.transactions.len(), 1);
+    assert_eq!(block_on(create_address(principal_id)).map(|r| r.address), Err("this wallet already exist".to_string()));
+}
+
+#[test]
+fn get_address_of_unregistered_principal() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let other = Principal::from_text("2vxsx-fae").unwrap();
+    assert_eq!(get_address(other), Err("root key is not cached yet".to_string()));
 
-    let message_bytes: [u8; 32] = message[..].try_into().unwrap();
-    let message_bytes_32 = libsecp256k1::Message::parse(&message_bytes);
+    block_on(create_address(principal_id)).unwrap();
+    let address = get_address(other).unwrap();
 
-    let public_key = libsecp256k1::recover(&message_bytes_32, &signature_bytes_64, &recovery_id_byte).unwrap();
+    let user = get_caller_data(other, 1).unwrap();
+    assert_eq!(user.address, address);
+    assert!(user.transactions.transactions.is_empty());
+    assert_eq!(block_on(create_address(other)).unwrap().address, address);
+}
+
+#[test]
+fn deploy_contract_uses_next_nonce() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    for expected_nonce in 0..2 {
+        let res = block_on(deploy_contract(
+            principal_id,
+            vec![0x60, 0x80],
+            None,
+            chain_id,
+            U256::zero(),
+            100_000,
+            U256::zero(),
+        ))
+        .unwrap();
+        let tx = transaction::get_transaction(&res.tx, chain_id).unwrap();
+        assert_eq!(tx.get_nonce().unwrap(), expected_nonce);
+    }
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce, 2);
+    assert_eq!(user.transactions.transactions.len(), 2);
+}
+
+#[test]
+fn deploy_contract_returns_address() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let res_create = block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    let res = block_on(deploy_contract(
+        principal_id,
+        vec![0x60, 0x80],
+        None,
+        chain_id,
+        U256::zero(),
+        100_000,
+        U256::zero(),
+    ))
+    .unwrap();
+    assert_eq!(res.contract_address, get_create_address(&res_create.address, 0));
+}
 
-    let address = get_address_from_public_key(public_key.serialize_compressed().to_vec()).unwrap();
+#[test]
+fn deploy_contract_with_constructor() {
+    use crate::abi::{AbiValue, ConstructorCall};
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    let constructor = ConstructorCall {
+        signature: "constructor(uint256,bool)".to_string(),
+        args: vec![AbiValue::Uint("69".to_string()), AbiValue::Bool(true)],
+    };
+    let res = block_on(deploy_contract(
+        principal_id,
+        vec![0x60, 0x80],
+        Some(constructor),
+        chain_id,
+        U256::zero(),
+        100_000,
+        U256::zero(),
+    ))
+    .unwrap();
+
+    let tx = transaction::Transaction1559::try_from(res.tx).unwrap();
+    let mut expected = vec![0x60, 0x80];
+    expected.extend([vec![0; 31], vec![69], vec![0; 31], vec![1]].concat());
+    assert_eq!(tx.data, Bytes(expected));
+}
+
+#[test]
+fn deploy_contract_empty_bytecode() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let res = block_on(deploy_contract(principal_id, vec![], None, 1, U256::zero(), 100_000, U256::zero()));
+    assert_eq!(res.map(|r| r.tx), Err("Bytecode is empty".to_string()));
+
+    let user = get_caller_data(principal_id, 1).unwrap();
+    assert!(user.transactions.transactions.is_empty());
+}
+
+#[test]
+fn deploy_contract_create2_via_deployer() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    let bytecode = vec![0x60, 0x80];
+    let salt = vec![1; 32];
+    let res = block_on(deploy_contract_create2(
+        principal_id,
+        bytecode.clone(),
+        None,
+        salt.clone(),
+        chain_id,
+        U256::zero(),
+        100_000,
+        U256::zero(),
+    ))
+    .unwrap();
+
+    let init_code_hash = easy_hasher::easy_hasher::raw_keccak256(bytecode.clone()).to_vec();
+    let expected = get_create2_address(&DETERMINISTIC_DEPLOYER, &salt, &init_code_hash).unwrap();
+    assert_eq!(res.contract_address, expected);
+
+    let tx = transaction::Transaction1559::try_from(res.tx).unwrap();
+    assert_eq!(tx.to, Some(DETERMINISTIC_DEPLOYER));
+    assert_eq!(tx.data, Bytes([salt, bytecode].concat()));
+}
+
+#[test]
+fn deploy_contract_create2_invalid_salt() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let res = block_on(deploy_contract_create2(
+        principal_id,
+        vec![0x60, 0x80],
+        None,
+        vec![1; 4],
+        1,
+        U256::zero(),
+        100_000,
+        U256::zero(),
+    ));
+    assert_eq!(res.map(|r| r.contract_address), Err("Invalid salt".to_string()));
+}
+
+#[test]
+fn validate_transaction_valid() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+
+    let raw_tx = unsigned_eip1559_transaction(0);
+    let res = validate_transaction(raw_tx.clone(), 1, principal_id).unwrap();
+
+    assert_eq!(res.transaction.transaction_type, TransactionType::EIP1559);
+    assert_eq!(res.transaction.nonce, 0);
+    assert_eq!(res.transaction.to, Some("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap()));
+    assert_eq!(res.transaction.value, "5");
+    assert_eq!(res.transaction.max_fee_per_gas, Some("1000".to_string()));
+    assert_eq!(res.transaction.gas_price, None);
+    assert!(get_caller_data(principal_id, 1).is_none());
+
+    let signed = block_on(sign_transaction(raw_tx, 1, principal_id)).unwrap();
+    let tx = transaction::get_transaction(&signed.sign_tx, 1).unwrap();
+    assert_eq!(res.message_hash.as_bytes(), &tx.get_message_to_sign().unwrap()[..]);
+}
+
+#[test]
+fn validate_transaction_invalid() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+
+    let signed = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+    let result = validate_transaction(signed.sign_tx, 1, principal_id).map(|r| r.message_hash);
+    assert_eq!(result, Err("transaction is already signed".to_string()));
+
+    let result = validate_transaction(unsigned_eip1559_transaction(0), 1, principal_id).map(|r| r.message_hash);
+    assert_eq!(result, Err("nonce 0 is already in use".to_string()));
+
+    let result = validate_transaction(unsigned_eip1559_transaction(1), 5, principal_id).map(|r| r.message_hash);
+    assert_eq!(result, Err("chain id 1 does not match 5".to_string()));
+
+    let result = validate_transaction(unsigned_legacy_transaction(1), 1, principal_id).map(|r| r.message_hash);
+    assert_eq!(result, Err("gas limit 0 is below 21000".to_string()));
+
+    let tx = transaction::Transaction1559 {
+        chain_id: 1,
+        nonce: 1,
+        max_priority_fee_per_gas: U256::from(2),
+        max_fee_per_gas: U256::from(1),
+        gas_limit: 21_000,
+        to: Some(Address::default()),
+        ..Default::default()
+    };
+    let result = validate_transaction(tx.serialize().unwrap(), 1, principal_id).map(|r| r.message_hash);
+    assert_eq!(result, Err("max priority fee per gas exceeds max fee per gas".to_string()));
+}
+
+fn unsigned_eip1559_transaction(nonce: u64) -> Vec<u8> {
+    use primitive_types::U256;
+    let tx = transaction::Transaction1559 {
+        chain_id: 1,
+        nonce,
+        max_priority_fee_per_gas: U256::from(100),
+        gas_limit: 60_000,
+        max_fee_per_gas: U256::from(1_000),
+        to: Some("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap()),
+        value: U256::from(5),
+        data: "0xa9059cbb".parse().unwrap(),
+        access_list: vec![],
+        ..Default::default()
+    };
+    tx.serialize().unwrap()
+}
+
+#[test]
+fn replace_transaction_by_nonce() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+
+    let res = block_on(replace_transaction(principal_id, chain_id, TransactionRef::Nonce(0), Some(25))).unwrap();
+    let replaced = transaction::Transaction1559::try_from(res.tx).unwrap();
+
+    assert!(replaced.is_signed());
+    assert_eq!(replaced.nonce, 0);
+    assert_eq!(replaced.max_priority_fee_per_gas, U256::from(125));
+    assert_eq!(replaced.max_fee_per_gas, U256::from(1_250));
+    assert_eq!(replaced.value, U256::from(5));
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.transactions.len(), 2);
+    assert_eq!(user.transactions.nonce, 1);
+}
+
+#[test]
+fn cancel_transaction_by_hash() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let res_create = block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    let res_sign = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    let hash = utils::get_transaction_hash(&res_sign.sign_tx);
+
+    let res = block_on(cancel_transaction(principal_id, chain_id, TransactionRef::Hash(hash), None)).unwrap();
+    let cancellation = transaction::Transaction1559::try_from(res.tx).unwrap();
+
+    assert_eq!(cancellation.nonce, 0);
+    assert_eq!(cancellation.to, Some(res_create.address));
+    assert_eq!(cancellation.value, U256::zero());
+    assert!(cancellation.data.is_empty());
+    assert_eq!(cancellation.gas_limit, 21_000);
+    assert_eq!(cancellation.max_fee_per_gas, U256::from(1_100));
+}
+
+#[test]
+fn replace_confirmed_transaction() {
+    let expected = Err("nonce 0 can no longer be replaced".to_string());
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    confirm_nonce(principal_id, chain_id, 0).unwrap();
+
+    let result = block_on(replace_transaction(principal_id, chain_id, TransactionRef::Nonce(0), None));
+    assert_eq!(result.map(|r| r.tx), expected);
+
+    let result = block_on(replace_transaction(principal_id, chain_id, TransactionRef::Nonce(3), None));
+    assert_eq!(result.map(|r| r.tx), Err("transaction not found".to_string()));
+}
+
+#[test]
+fn sign_transactions_batch_across_chains() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let requests = vec![
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(0), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(1), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(0), chain_id: 1, assign_nonce: true },
+        SignTransactionRequest { hex_raw_tx: unsigned_legacy_transaction(0), chain_id: 5, assign_nonce: false },
+    ];
+    let results = block_on(sign_transactions_batch(requests, principal_id));
+    assert_eq!(results.len(), 4);
+
+    let nonces = results
+        .iter()
+        .zip([1, 1, 1, 5])
+        .map(|(result, chain_id)| {
+            let signed = &result.as_ref().unwrap().sign_tx;
+            let tx = transaction::get_transaction(signed, chain_id).unwrap();
+            assert!(tx.is_signed());
+            tx.get_nonce().unwrap()
+        })
+        .collect::<Vec<u64>>();
+    assert_eq!(nonces, vec![0, 1, 2, 0]);
+
+    assert_eq!(get_caller_data(principal_id, 1).unwrap().transactions.nonce, 3);
+    assert_eq!(get_caller_data(principal_id, 5).unwrap().transactions.nonce, 1);
+}
+
+#[test]
+fn sign_transactions_batch_with_failed_item() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+
+    let requests = vec![
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(0), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(4), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: vec![], chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(1), chain_id: 1, assign_nonce: false },
+    ];
+    let results = block_on(sign_transactions_batch(requests, principal_id));
+
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap_err(), "nonce 4 leaves a gap, expected 1");
+    assert_eq!(results[2].as_ref().unwrap_err(), "Invalid type");
+    assert!(results[3].is_ok());
+
+    let user = get_caller_data(principal_id, 1).unwrap();
+    assert_eq!(user.transactions.transactions.len(), 2);
+    assert_eq!(user.transactions.nonce, 2);
+}
+
+/// Signs like the management canister, except for one call that fails.
+struct FailingSigner {
+    calls: std::cell::Cell<u32>,
+    fail_on: u32,
+}
+
+impl signer::Signer for FailingSigner {
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> signer::SignerFuture<'_, ExtendedPublicKey> {
+        signer::ManagementCanisterSigner.public_key(derivation_path)
+    }
+
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> signer::SignerFuture<'_, Vec<u8>> {
+        self.calls.set(self.calls.get() + 1);
+        if self.calls.get() == self.fail_on {
+            return Box::pin(async { Err("signing failed".to_string()) });
+        }
+        signer::ManagementCanisterSigner.sign_digest(derivation_path, message_hash)
+    }
+}
+
+/// Signs like the management canister, but returns truncated signatures.
+struct MalformedSigner;
+
+impl signer::Signer for MalformedSigner {
+    fn public_key(&self, derivation_path: Vec<Vec<u8>>) -> signer::SignerFuture<'_, ExtendedPublicKey> {
+        signer::ManagementCanisterSigner.public_key(derivation_path)
+    }
+
+    fn sign_digest(&self, derivation_path: Vec<Vec<u8>>, message_hash: Vec<u8>) -> signer::SignerFuture<'_, Vec<u8>> {
+        let signature = signer::ManagementCanisterSigner.sign_digest(derivation_path, message_hash);
+        Box::pin(async move { Ok(signature.await?[..63].to_vec()) })
+    }
+
+    fn sign_digest_with_cost(
+        &self,
+        derivation_path: Vec<Vec<u8>>,
+        message_hash: Vec<u8>,
+    ) -> signer::SignerFuture<'_, (Vec<u8>, u64)> {
+        let signature = self.sign_digest(derivation_path, message_hash);
+        Box::pin(async move { Ok((signature.await?, 100)) })
+    }
+}
+
+#[test]
+fn malformed_signature_leaves_balance_and_nonce() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    let chain_id = 1;
+    init_controller(principal_id);
+    STATE.with(|s| s.borrow_mut().config.sign_cycles = 100);
+    block_on(credit_cycles(principal_id, 250)).unwrap();
+    signer::set_signer(std::rc::Rc::new(MalformedSigner));
+
+    let result = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id));
+    assert_eq!(result.map(|r| r.sign_tx), Err("Invalid signature".to_string()));
+
+    let result = block_on(deploy_contract(
+        principal_id,
+        vec![0x60, 0x00],
+        None,
+        chain_id,
+        U256::from(1),
+        60_000,
+        U256::from(100),
+    ));
+    assert_eq!(result.map(|r| r.tx), Err("Invalid signature".to_string()));
+
+    set_raw_signing(principal_id, true).unwrap();
+    block_on(set_user_raw_signing(principal_id, true)).unwrap();
+    let result = block_on(sign_hash(principal_id, vec![7; 32]));
+    assert_eq!(result.map(|r| r.v), Err("Invalid signature".to_string()));
+
+    assert_eq!(get_cycles_balance(principal_id), 250);
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert!(user.transactions.transactions.is_empty());
+    assert!(user.transactions.nonce_manager.pending.is_empty());
+    assert_eq!(user.transactions.nonce_manager.next_nonce(), 0);
+}
+
+#[test]
+fn sign_transactions_batch_with_failed_signature() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+    signer::set_signer(std::rc::Rc::new(FailingSigner { calls: Default::default(), fail_on: 2 }));
+
+    let requests = vec![
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(0), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(1), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_eip1559_transaction(2), chain_id: 1, assign_nonce: false },
+        SignTransactionRequest { hex_raw_tx: unsigned_legacy_transaction(0), chain_id: 5, assign_nonce: false },
+    ];
+    let results = block_on(sign_transactions_batch(requests, principal_id));
+
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap_err(), "signing failed");
+    assert_eq!(results[2].as_ref().unwrap_err(), "an earlier transaction for chain 1 failed");
+    assert!(results[3].is_ok());
+
+    // nonce 1 is free again and nothing above it is held
+    let user = get_caller_data(principal_id, 1).unwrap();
+    assert_eq!(user.transactions.transactions.len(), 1);
+    assert_eq!(user.transactions.nonce_manager.pending.len(), 1);
+    assert_eq!(user.transactions.nonce_manager.check(1), Ok(()));
+    block_on(sign_transaction(unsigned_eip1559_transaction(1), 1, principal_id)).unwrap();
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn broadcast_transaction_marks_nonce() {
+    use crate::mocks::StubTransport;
+    use crate::nonce::NonceStatus;
+    use serde_json::json;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    let res_sign = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+
+    let transport = StubTransport::default().with_result("eth_sendRawTransaction", json!("0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b"));
+    let res = block_on(broadcast_transaction(principal_id, chain_id, res_sign.sign_tx, &transport)).unwrap();
+    assert_eq!(res.hash.to_string(), "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b");
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce_manager.status(0), Some(NonceStatus::Broadcast));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn broadcast_transaction_already_known() {
+    use crate::mocks::StubTransport;
+    use crate::nonce::NonceStatus;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    let res_sign = block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+
+    let expected = H256::try_from(&utils::get_transaction_hash(&res_sign.sign_tx)[..]).unwrap();
+    let transport = StubTransport::default().with_error("eth_sendRawTransaction", "already known");
+    let res = block_on(broadcast_transaction(principal_id, chain_id, res_sign.sign_tx, &transport)).unwrap();
+    assert_eq!(res.hash, expected);
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce_manager.status(0), Some(NonceStatus::Broadcast));
+}
+
+#[test]
+fn set_chain_config_without_providers() {
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let result = set_chain_config(principal_id, 1, ChainConfig::default());
+    assert_eq!(result, Err("at least one RPC provider is required".to_string()));
+    assert!(get_chain_config(1).is_err());
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn broadcast_transaction_unknown_chain() {
+    use crate::mocks::StubTransport;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    block_on(create_address(principal_id)).unwrap();
+    let res_sign = block_on(sign_transaction(unsigned_eip1559_transaction(0), 1, principal_id)).unwrap();
+
+    let transport = StubTransport::default();
+    let result = block_on(broadcast_transaction(principal_id, 1, res_sign.sign_tx, &transport));
+    assert_eq!(result.map(|r| r.hash), Err("chain 1 is not registered".to_string()));
+    assert!(transport.requests.borrow().is_empty());
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn get_balances() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let res_create = block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+
+    let transport = StubTransport::default()
+        .with_result("eth_getBalance", json!("0xde0b6b3a7640000"))
+        .with_result("eth_call", json!(format!("0x{:064x}", 42)));
+
+    let balance = block_on(get_balance(principal_id, chain_id, &transport)).unwrap();
+    assert_eq!(balance, U256::from(1_000_000_000_000_000_000u64));
+
+    let contract_address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap();
+    let balance = block_on(get_erc_20_balance(principal_id, chain_id, contract_address, &transport)).unwrap();
+    assert_eq!(balance, U256::from(42));
+
+    let requests = transport.requests.borrow();
+    assert_eq!(requests[0].1["params"], json!([res_create.address, "latest"]));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn sync_nonce_from_chain() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let res_create = block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+
+    let transport = StubTransport::default().with_result("eth_getTransactionCount", json!("0x5"));
+    let nonce = block_on(sync_nonce(principal_id, chain_id, &transport)).unwrap();
+    assert_eq!(nonce, 5);
+
+    let requests = transport.requests.borrow();
+    assert_eq!(requests[0].1["params"], json!([res_create.address, "latest"]));
+    assert_eq!(requests[1].1["params"], json!([res_create.address, "pending"]));
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.nonce, 5);
+    assert!(user.transactions.nonce_manager.pending.is_empty());
+
+    let entry = audit::read_entries(0, 100).unwrap().pop().unwrap();
+    assert_eq!(entry.operation, AuditOperation::SyncNonce);
+    assert_eq!(entry.chain_id, Some(chain_id));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn sync_nonce_keeps_mempool_transactions_replaceable() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let address = block_on(create_address(principal_id)).unwrap().address;
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    for nonce in 0..4 {
+        block_on(sign_transaction(unsigned_eip1559_transaction(nonce), chain_id, principal_id)).unwrap();
+    }
+
+    // nonce 0 is mined, 1 is in the mempool, 2 and 3 were not broadcast yet
+    let transport = StubTransport::default()
+        .with_params_result("eth_getTransactionCount", json!([address, "latest"]), json!("0x1"))
+        .with_params_result("eth_getTransactionCount", json!([address, "pending"]), json!("0x2"));
+    let nonce = block_on(sync_nonce(principal_id, chain_id, &transport)).unwrap();
+    assert_eq!(nonce, 4);
+
+    block_on(replace_transaction(principal_id, chain_id, TransactionRef::Nonce(1), None)).unwrap();
+    release_nonce(principal_id, chain_id, 3).unwrap();
+    release_nonce(principal_id, chain_id, 2).unwrap();
+    assert_eq!(reserve_nonce(principal_id, chain_id), Ok(2));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn transfer_erc_20_with_auto_sync_nonce() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig {
+            rpc_providers: vec!["https://rpc.example.com".to_string()],
+            auto_sync_nonce: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let transport = Rc::new(StubTransport::default().with_result("eth_getTransactionCount", json!("0x3")));
+    rpc::set_transport(transport.clone());
+
+    let res = block_on(transfer_erc_20(
+        principal_id,
+        chain_id,
+        U256::zero(),
+        60_000,
+        U256::zero(),
+        "0x80d6cae8b397e4a6e578c79d628af5cff8e13507".parse().unwrap(),
+        U256::one(),
+        "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap(),
+    ))
+    .unwrap();
+
+    let tx = transaction::get_transaction(&res.tx, chain_id).unwrap();
+    assert_eq!(tx.get_nonce().unwrap(), 3);
+    assert_eq!(transport.requests.borrow().len(), 2);
+
+    // the first transfer is not broadcast yet, so the chain still reports 3
+    let res = block_on(transfer_erc_20(
+        principal_id,
+        chain_id,
+        U256::zero(),
+        60_000,
+        U256::zero(),
+        "0x80d6cae8b397e4a6e578c79d628af5cff8e13507".parse().unwrap(),
+        U256::one(),
+        "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap(),
+    ))
+    .unwrap();
+    let tx = transaction::get_transaction(&res.tx, chain_id).unwrap();
+    assert_eq!(tx.get_nonce().unwrap(), 4);
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn transfer_erc_20_with_estimated_fees_valid() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    let res_create = block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    let transport = Rc::new(
+        StubTransport::default()
+            .with_result("eth_feeHistory", json!({ "baseFeePerGas": ["0x64", "0x64"], "reward": [["0x2"]] }))
+            .with_result("eth_estimateGas", json!("0xc350")),
+    );
+    rpc::set_transport(transport.clone());
+
+    let contract_address: Address = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap();
+    let res = block_on(transfer_erc_20_with_estimated_fees(
+        principal_id,
+        chain_id,
+        "0x80d6cae8b397e4a6e578c79d628af5cff8e13507".parse().unwrap(),
+        U256::one(),
+        contract_address,
+    ))
+    .unwrap();
+
+    let tx = transaction::Transaction1559::try_from(res.tx).unwrap();
+    assert_eq!(tx.max_priority_fee_per_gas, U256::from(2));
+    assert_eq!(tx.max_fee_per_gas, U256::from(202));
+    assert_eq!(tx.gas_limit, 60_000);
+
+    let requests = transport.requests.borrow();
+    assert_eq!(requests[1].1["params"][0]["from"], json!(res_create.address));
+    assert_eq!(requests[1].1["params"][0]["to"], json!(contract_address));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn deploy_contract_with_estimated_fees_without_base_fee() {
+    use crate::mocks::StubTransport;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    let transport = Rc::new(
+        StubTransport::default()
+            .with_result("eth_feeHistory", json!({ "oldestBlock": "0x10", "reward": [] }))
+            .with_result("eth_gasPrice", json!("0x3b9aca00"))
+            .with_result("eth_estimateGas", json!("0xc350")),
+    );
+    rpc::set_transport(transport);
+
+    let res = block_on(deploy_contract_with_estimated_fees(principal_id, vec![0x60, 0x80], None, chain_id)).unwrap();
+
+    assert!(res.tx[0] >= 0xc0);
+    let tx = transaction::TransactionLegacy::try_from((res.tx, chain_id)).unwrap();
+    assert_eq!(tx.gas_price, U256::from(1_000_000_000));
+    assert_eq!(tx.gas_limit, 60_000);
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn poll_receipts_records_outcome() {
+    use crate::mocks::StubTransport;
+    use crate::nonce::NonceStatus;
+    use serde_json::json;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    block_on(replace_transaction(principal_id, chain_id, TransactionRef::Nonce(0), None)).unwrap();
+    mark_nonce_broadcast(principal_id, chain_id, 0).unwrap();
+
+    let transport = StubTransport::default().with_result(
+        "eth_getTransactionReceipt",
+        json!({ "blockNumber": "0x20", "status": "0x1", "gasUsed": "0xea60", "effectiveGasPrice": "0x64" }),
+    );
+    assert_eq!(block_on(poll_receipts(&transport)), 1);
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    let history = &user.transactions.transactions;
+    assert_eq!(history[0].status, TransactionStatus::Success);
+    assert_eq!(
+        history[0].receipt,
+        Some(TransactionReceipt { block_number: 32, gas_used: 60_000, effective_gas_price: Some(100) })
+    );
+    assert_eq!(history[1].status, TransactionStatus::Dropped);
+    assert_eq!(user.transactions.nonce_manager.status(0), Some(NonceStatus::Confirmed));
+
+    assert_eq!(block_on(poll_receipts(&transport)), 0);
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn poll_receipts_drops_expired() {
+    use crate::mocks::StubTransport;
+    use crate::nonce::NonceStatus;
+    use serde_json::{json, Value};
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig {
+            rpc_providers: vec!["https://rpc.example.com".to_string()],
+            receipt_timeout_ns: 1_000,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(1), chain_id, principal_id)).unwrap();
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let chain_data = state.users.get_mut(&principal_id).unwrap().transactions.get_mut(&chain_id).unwrap();
+        chain_data.transactions[0].timestamp = 0;
+    });
+    mark_nonce_broadcast(principal_id, chain_id, 0).unwrap();
+    mark_nonce_broadcast(principal_id, chain_id, 1).unwrap();
+
+    let transport = StubTransport::default().with_result("eth_getTransactionReceipt", Value::Null);
+    assert_eq!(block_on(poll_receipts(&transport)), 1);
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    let statuses = user.transactions.transactions.iter().map(|tx| tx.status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![TransactionStatus::Dropped, TransactionStatus::Pending]);
+    assert_eq!(user.transactions.nonce_manager.status(0), None);
+    assert_eq!(user.transactions.nonce_manager.status(1), Some(NonceStatus::Broadcast));
+    assert_eq!(transport.requests.borrow()[0].1["params"], json!(["0x".to_owned() + &vec_u8_to_string(&user.transactions.transactions[0].hash)]));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn poll_receipts_drops_expired_after_errors() {
+    use crate::mocks::StubTransport;
+    use crate::nonce::NonceStatus;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig {
+            rpc_providers: vec!["https://rpc.example.com".to_string()],
+            receipt_timeout_ns: 1_000,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(1), chain_id, principal_id)).unwrap();
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let chain_data = state.users.get_mut(&principal_id).unwrap().transactions.get_mut(&chain_id).unwrap();
+        chain_data.transactions[0].timestamp = 0;
+    });
+    mark_nonce_broadcast(principal_id, chain_id, 0).unwrap();
+    mark_nonce_broadcast(principal_id, chain_id, 1).unwrap();
+
+    let transport = StubTransport::default().with_error("eth_getTransactionReceipt", "header not found");
+    assert_eq!(block_on(poll_receipts(&transport)), 1);
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    let statuses = user.transactions.transactions.iter().map(|tx| tx.status).collect::<Vec<_>>();
+    assert_eq!(statuses, vec![TransactionStatus::Dropped, TransactionStatus::Pending]);
+    assert_eq!(user.transactions.nonce_manager.status(1), Some(NonceStatus::Broadcast));
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn poll_receipts_skips_unbroadcast() {
+    use crate::mocks::StubTransport;
+    use serde_json::Value;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig {
+            rpc_providers: vec!["https://rpc.example.com".to_string()],
+            receipt_timeout_ns: 1_000,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    block_on(sign_transaction(unsigned_eip1559_transaction(0), chain_id, principal_id)).unwrap();
+    STATE.with(|s| {
+        let mut state = s.borrow_mut();
+        let chain_data = state.users.get_mut(&principal_id).unwrap().transactions.get_mut(&chain_id).unwrap();
+        chain_data.transactions[0].timestamp = 0;
+    });
+
+    let transport = StubTransport::default().with_result("eth_getTransactionReceipt", Value::Null);
+    assert_eq!(block_on(poll_receipts(&transport)), 0);
+    assert!(transport.requests.borrow().is_empty());
+
+    let user = get_caller_data(principal_id, chain_id).unwrap();
+    assert_eq!(user.transactions.transactions[0].status, TransactionStatus::Pending);
+}
+
+#[cfg(feature = "rpc")]
+#[test]
+fn poll_receipts_caps_requests() {
+    use crate::mocks::StubTransport;
+    use serde_json::Value;
+
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    init_controller(principal_id);
+    block_on(create_address(principal_id)).unwrap();
+
+    let chain_id: u64 = 1;
+    set_chain_config(
+        principal_id,
+        chain_id,
+        ChainConfig { rpc_providers: vec!["https://rpc.example.com".to_string()], ..Default::default() },
+    )
+    .unwrap();
+    let count = MAX_RECEIPTS_PER_POLL as u64 + 5;
+    for nonce in 0..count {
+        block_on(sign_transaction(unsigned_eip1559_transaction(nonce), chain_id, principal_id)).unwrap();
+        mark_nonce_broadcast(principal_id, chain_id, nonce).unwrap();
+    }
 
-    Ok(address)
+    let transport = StubTransport::default().with_result("eth_getTransactionReceipt", Value::Null);
+    assert_eq!(block_on(poll_receipts(&transport)), 0);
+    assert_eq!(transport.requests.borrow().len(), MAX_RECEIPTS_PER_POLL);
 }
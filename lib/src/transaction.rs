@@ -1,13 +1,15 @@
-use crate::utils::{
-    remove_leading, string_to_vec_u8, u256_to_vec_u8, u64_to_vec_u8, vec_u8_to_string,
-    vec_u8_to_u256, vec_u8_to_u64,
-};
+use crate::types::{AccessListItem, Address, Bytes, H256};
+use crate::utils::{u256_to_vec_u8, u64_to_vec_u8, vec_u8_to_u64};
 use easy_hasher::easy_hasher;
+use ic_cdk::export::{
+    candid::CandidType,
+    serde::{Deserialize, Serialize},
+};
 
 use primitive_types::U256;
 
-#[derive(Debug, Clone, PartialEq)]
-enum TransactionType {
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TransactionType {
     Legacy,
     EIP1559,
     EIP2930,
@@ -20,79 +22,110 @@ pub trait Sign {
     fn get_signature(&self) -> Result<Vec<u8>, String>;
     fn get_recovery_id(&self) -> Result<u8, String>;
     fn get_nonce(&self) -> Result<u64, String>;
+    fn set_nonce(&mut self, nonce: u64);
     fn serialize(&self) -> Result<Vec<u8>, String>;
+    fn clear_signature(&mut self);
+    fn bump_fees(&mut self, percent: u64) -> Result<(), String>;
+    fn make_cancellation(&mut self, address: &Address);
+    fn get_summary(&self) -> TransactionSummary;
 }
 
-pub struct TransactionLegacy {
+/// Decoded fields of a transaction. Amounts are decimal strings and the fee
+/// fields that do not apply to the transaction type are `None`.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransactionSummary {
+    pub transaction_type: TransactionType,
     pub chain_id: u64,
     pub nonce: u64,
-    pub gas_price: U256,
+    pub to: Option<Address>,
+    pub value: String,
+    pub data: Bytes,
     pub gas_limit: u64,
-    pub to: String,
-    pub value: U256,
-    pub data: String,
-    pub v: String,
-    pub r: String,
-    pub s: String,
+    pub gas_price: Option<String>,
+    pub max_fee_per_gas: Option<String>,
+    pub max_priority_fee_per_gas: Option<String>,
+    pub access_list: Vec<AccessListItem>,
 }
-impl From<(Vec<u8>, u64)> for TransactionLegacy {
-    fn from(data: (Vec<u8>, u64)) -> Self {
-        let rlp = rlp::Rlp::new(&data.0[..]);
-
-        let nonce_hex: Vec<u8> = rlp.at(0).as_val::<Vec<u8>>();
-        let nonce = vec_u8_to_u64(&nonce_hex);
-
-        let gas_price_hex = rlp.at(1).as_val::<Vec<u8>>();
-        let gas_price = vec_u8_to_u256(&gas_price_hex);
-
-        let gas_limit_hex = rlp.at(2).as_val::<Vec<u8>>();
-        let gas_limit = vec_u8_to_u64(&gas_limit_hex);
-
-        let to_hex = rlp.at(3).as_val::<Vec<u8>>();
-        let to = vec_u8_to_string(&to_hex);
-
-        let value_hex = rlp.at(4).as_val::<Vec<u8>>();
-        let value = vec_u8_to_u256(&value_hex);
-
-        let data_tx_hex = rlp.at(5).as_val::<Vec<u8>>();
-        let data_tx = vec_u8_to_string(&data_tx_hex);
-
-        let v_hex = rlp.at(6).as_val::<Vec<u8>>();
-        let v = vec_u8_to_string(&v_hex);
-
-        let r_hex = rlp.at(7).as_val::<Vec<u8>>();
-        let r = vec_u8_to_string(&r_hex);
 
-        let s_hex = rlp.at(8).as_val::<Vec<u8>>();
-        let s = vec_u8_to_string(&s_hex);
+/// Minimum fee increase most mempools require to accept a replacement transaction.
+pub const MIN_FEE_BUMP_PERCENT: u64 = 10;
 
-        let chain_id =data.1;
+/// Intrinsic gas of a plain transfer, the lowest gas limit any transaction can use.
+pub const MIN_GAS_LIMIT: u64 = 21_000;
 
-        TransactionLegacy {
+/// `to` is `None` for contract creation. `v`, `r` and `s` are zero until the
+/// transaction is signed. A `chain_id` of zero signs without EIP-155 replay
+/// protection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionLegacy {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: U256,
+    pub gas_limit: u64,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
+}
+impl TryFrom<(Vec<u8>, u64)> for TransactionLegacy {
+    type Error = String;
+
+    fn try_from(data: (Vec<u8>, u64)) -> Result<Self, Self::Error> {
+        let rlp = decode_list(&data.0[..], 9)?;
+        let v = decode_u64(&rlp, 6)?;
+        let r = decode_u256(&rlp, 7)?;
+        let s = decode_u256(&rlp, 8)?;
+        // a signed v of 27 or 28 means the transaction has no replay protection
+        let is_signed = !r.is_zero() || !s.is_zero();
+        let chain_id = if is_signed && (v == 27 || v == 28) { 0 } else { data.1 };
+
+        Ok(TransactionLegacy {
             chain_id,
-            nonce,
-            gas_price,
-            gas_limit,
-            to,
-            value,
-            data: data_tx,
+            nonce: decode_u64(&rlp, 0)?,
+            gas_price: decode_u256(&rlp, 1)?,
+            gas_limit: decode_u64(&rlp, 2)?,
+            to: decode_to(&rlp, 3)?,
+            value: decode_u256(&rlp, 4)?,
+            data: Bytes(decode_bytes(&rlp, 5)?),
             v,
             r,
             s,
+        })
+    }
+}
+impl TransactionLegacy {
+    fn get_v_offset(&self) -> u64 {
+        if self.chain_id == 0 {
+            27
+        } else {
+            self.chain_id * 2 + 35
         }
     }
 }
 impl Sign for TransactionLegacy {
     fn get_message_to_sign(&self) -> Result<Vec<u8>, String> {
+        if self.chain_id == 0 {
+            let mut stream = rlp::RlpStream::new_list(6);
+            stream.append(&u64_to_vec_u8(&self.nonce));
+            stream.append(&u256_to_vec_u8(&self.gas_price));
+            stream.append(&u64_to_vec_u8(&self.gas_limit));
+            stream.append(&encode_to(&self.to));
+            stream.append(&u256_to_vec_u8(&self.value));
+            stream.append(&self.data.0);
+            return Ok(easy_hasher::raw_keccak256(stream.out()).to_vec());
+        }
+
         let mut stream = rlp::RlpStream::new_list(9);
 
         let items = [
             u64_to_vec_u8(&self.nonce),
             u256_to_vec_u8(&self.gas_price),
             u64_to_vec_u8(&self.gas_limit),
-            string_to_vec_u8(&self.to),
+            encode_to(&self.to),
             u256_to_vec_u8(&self.value),
-            string_to_vec_u8(&self.data),
+            self.data.0.clone(),
             u64_to_vec_u8(&self.chain_id),
         ];
 
@@ -110,63 +143,35 @@ impl Sign for TransactionLegacy {
         Ok(keccak256.to_vec())
     }
     fn sign(&mut self, signature: Vec<u8>, public_key: Vec<u8>) -> Result<Vec<u8>, String> {
-        let chain_id = u64::try_from(self.chain_id).unwrap();
-
-        let r_remove_leading_zeros = remove_leading(signature[..32].to_vec(), 0);
-        let s_remove_leading_zeros = remove_leading(signature[32..].to_vec(), 0);
+        let signature = normalize_signature(&signature)?;
 
-        let r = vec_u8_to_string(&r_remove_leading_zeros);
+        let message = self.get_message_to_sign()?;
+        let recovery_id = get_recovery_id(&message, &signature, &public_key)?;
 
-        let s = vec_u8_to_string(&s_remove_leading_zeros);
+        self.v = self.get_v_offset() + u64::from(recovery_id);
+        self.r = U256::from_big_endian(&signature[..32]);
+        self.s = U256::from_big_endian(&signature[32..]);
 
-        let message = self.get_message_to_sign().unwrap();
-        let recovery_id = get_recovery_id(&message, &signature, &public_key).unwrap();
-
-        let v_number = chain_id * 2 + 35 + u64::try_from(recovery_id).unwrap();
-        let v = vec_u8_to_string(&u64_to_vec_u8(&v_number));
-
-        self.v = v;
-        self.r = r;
-        self.s = s;
-
-        let result = self.serialize().unwrap();
-        Ok(result)
+        self.serialize()
     }
     fn is_signed(&self) -> bool {
-        let r: String;
-        if self.r.starts_with("0x") {
-            r = self.r[2..].to_string();
-        } else {
-            r = self.r[..].to_string();
-        }
-        let s: String;
-        if self.s.starts_with("0x") {
-            s = self.s[2..].to_string();
-        } else {
-            s = self.s[..].to_string();
-        }
-
-        r != "00" || s != "00"
+        !self.r.is_zero() || !self.s.is_zero()
     }
     fn get_signature(&self) -> Result<Vec<u8>, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-
-        let r = string_to_vec_u8(&self.r);
-        let s = string_to_vec_u8(&self.s);
-
-        Ok([&r[..], &s[..]].concat())
+        Ok(join_signature(self.r, self.s))
     }
     fn get_recovery_id(&self) -> Result<u8, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-        let chain_id = i64::try_from(self.chain_id).unwrap();
-        let v = string_to_vec_u8(&self.v);
 
-        let recovery_id = -1 * ((chain_id * 2) + 35 - i64::try_from(v[0]).unwrap());
-        Ok(u8::try_from(recovery_id).unwrap())
+        match self.v.checked_sub(self.get_v_offset()) {
+            Some(recovery_id) if recovery_id < 2 => Ok(recovery_id as u8),
+            _ => Err("Invalid v for this chain id".to_string()),
+        }
     }
     fn serialize(&self) -> Result<Vec<u8>, String> {
         let mut stream = rlp::RlpStream::new_list(9);
@@ -180,22 +185,21 @@ impl Sign for TransactionLegacy {
         let gas_limit = u64_to_vec_u8(&self.gas_limit);
         stream.append(&gas_limit);
 
-        let to = string_to_vec_u8(&self.to[..]);
+        let to = encode_to(&self.to);
         stream.append(&to);
 
         let value = u256_to_vec_u8(&self.value);
         stream.append(&value);
 
-        let data = string_to_vec_u8(&self.data[..]);
-        stream.append(&data);
+        stream.append(&self.data.0);
 
-        let v = string_to_vec_u8(&self.v[..]);
+        let v = u64_to_vec_u8(&self.v);
         stream.append(&v);
 
-        let r = string_to_vec_u8(&self.r[..]);
+        let r = u256_to_vec_u8(&self.r);
         stream.append(&r);
 
-        let s = string_to_vec_u8(&self.s[..]);
+        let s = u256_to_vec_u8(&self.s);
         stream.append(&s);
 
         Ok(stream.out().to_vec())
@@ -203,69 +207,74 @@ impl Sign for TransactionLegacy {
     fn get_nonce(&self) -> Result<u64, String> {
         Ok(self.nonce)
     }
+    fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+    fn clear_signature(&mut self) {
+        self.v = 0;
+        self.r = U256::zero();
+        self.s = U256::zero();
+    }
+    fn bump_fees(&mut self, percent: u64) -> Result<(), String> {
+        self.gas_price = bump_fee(self.gas_price, percent)?;
+        Ok(())
+    }
+    fn make_cancellation(&mut self, address: &Address) {
+        self.to = Some(*address);
+        self.value = U256::zero();
+        self.data = Bytes::default();
+        self.gas_limit = CANCELLATION_GAS_LIMIT;
+    }
+    fn get_summary(&self) -> TransactionSummary {
+        TransactionSummary {
+            transaction_type: TransactionType::Legacy,
+            chain_id: self.chain_id,
+            nonce: self.nonce,
+            to: self.to,
+            value: self.value.to_string(),
+            data: self.data.clone(),
+            gas_limit: self.gas_limit,
+            gas_price: Some(self.gas_price.to_string()),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: vec![],
+        }
+    }
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Transaction2930 {
     pub chain_id: u64,
     pub nonce: u64,
     pub gas_price: U256,
     pub gas_limit: u64,
-    pub to: String,
+    pub to: Option<Address>,
     pub value: U256,
-    pub data: String,
-    pub access_list: Vec<(String, Vec<String>)>,
-    pub v: String,
-    pub r: String,
-    pub s: String,
+    pub data: Bytes,
+    pub access_list: Vec<AccessListItem>,
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
 }
-impl From<Vec<u8>> for Transaction2930 {
-    fn from(data: Vec<u8>) -> Self {
-        let rlp = rlp::Rlp::new(&data[1..]);
-
-        let chain_id_hex = rlp.at(0).as_val::<Vec<u8>>();
-        let chain_id = vec_u8_to_u64(&chain_id_hex);
-
-        let nonce_hex = rlp.at(1).as_val::<Vec<u8>>();
-        let nonce = vec_u8_to_u64(&nonce_hex);
-
-        let gas_price_hex = rlp.at(2).as_val::<Vec<u8>>();
-        let gas_price = vec_u8_to_u256(&gas_price_hex);
-
-        let gas_limit_hex = rlp.at(3).as_val::<Vec<u8>>();
-        let gas_limit = vec_u8_to_u64(&gas_limit_hex);
-
-        let to_hex = rlp.at(4).as_val::<Vec<u8>>();
-        let to = vec_u8_to_string(&to_hex);
-
-        let value_hex = rlp.at(5).as_val::<Vec<u8>>();
-        let value = vec_u8_to_u256(&value_hex);
-
-        let data_tx_hex = rlp.at(6).as_val::<Vec<u8>>();
-        let data_tx = vec_u8_to_string(&data_tx_hex);
-
-        let access_list = decode_access_list(&rlp.at(7).as_raw().to_vec());
-
-        let v_hex = rlp.at(8).as_val::<Vec<u8>>();
-        let v = vec_u8_to_string(&v_hex);
-
-        let r_hex = rlp.at(9).as_val::<Vec<u8>>();
-        let r = vec_u8_to_string(&r_hex);
-
-        let s_hex = rlp.at(10).as_val::<Vec<u8>>();
-        let s = vec_u8_to_string(&s_hex);
-        Transaction2930 {
-            chain_id,
-            nonce,
-            gas_price,
-            gas_limit,
-            to,
-            data: data_tx,
-            value,
-            access_list,
-            v,
-            r,
-            s,
-        }
+impl TryFrom<Vec<u8>> for Transaction2930 {
+    type Error = String;
+
+    fn try_from(data: Vec<u8>) -> Result<Self, Self::Error> {
+        let rlp = decode_list(data.get(1..).unwrap_or_default(), 11)?;
+
+        Ok(Transaction2930 {
+            chain_id: decode_u64(&rlp, 0)?,
+            nonce: decode_u64(&rlp, 1)?,
+            gas_price: decode_u256(&rlp, 2)?,
+            gas_limit: decode_u64(&rlp, 3)?,
+            to: decode_to(&rlp, 4)?,
+            value: decode_u256(&rlp, 5)?,
+            data: Bytes(decode_bytes(&rlp, 6)?),
+            access_list: decode_access_list(&rlp, 7)?,
+            v: decode_u64(&rlp, 8)?,
+            r: decode_u256(&rlp, 9)?,
+            s: decode_u256(&rlp, 10)?,
+        })
     }
 }
 impl Sign for Transaction2930 {
@@ -276,9 +285,9 @@ impl Sign for Transaction2930 {
             u64_to_vec_u8(&self.nonce),
             u256_to_vec_u8(&self.gas_price),
             u64_to_vec_u8(&self.gas_limit),
-            string_to_vec_u8(&self.to),
+            encode_to(&self.to),
             u256_to_vec_u8(&self.value),
-            string_to_vec_u8(&self.data),
+            self.data.0.clone(),
         ];
 
         for item in items {
@@ -295,67 +304,31 @@ impl Sign for Transaction2930 {
         Ok(keccak256.to_vec())
     }
     fn sign(&mut self, signature: Vec<u8>, public_key: Vec<u8>) -> Result<Vec<u8>, String> {
-        let r_remove_leading_zeros = remove_leading(signature[..32].to_vec(), 0);
-        let s_remove_leading_zeros = remove_leading(signature[32..].to_vec(), 0);
+        let signature = normalize_signature(&signature)?;
 
-        let r = vec_u8_to_string(&r_remove_leading_zeros);
+        let message = self.get_message_to_sign()?;
+        let recovery_id = get_recovery_id(&message, &signature, &public_key)?;
 
-        let s = vec_u8_to_string(&s_remove_leading_zeros);
-
-        let message = self.get_message_to_sign().unwrap();
-        let recovery_id = get_recovery_id(&message, &signature, &public_key).unwrap();
-        let v: String;
-        if recovery_id == 0 {
-            v = "".to_string();
-        } else {
-            v = "01".to_string();
-        }
+        self.v = u64::from(recovery_id);
+        self.r = U256::from_big_endian(&signature[..32]);
+        self.s = U256::from_big_endian(&signature[32..]);
 
-        self.v = v;
-        self.r = r;
-        self.s = s;
-
-        let result = self.serialize().unwrap();
-        Ok(result)
+        self.serialize()
     }
     fn is_signed(&self) -> bool {
-        let r: String;
-        if self.r.starts_with("0x") {
-            r = self.r[2..].to_string();
-        } else {
-            r = self.r[..].to_string();
-        }
-        let s: String;
-        if self.s.starts_with("0x") {
-            s = self.s[2..].to_string();
-        } else {
-            s = self.s[..].to_string();
-        }
-
-        r != "00" || s != "00"
+        !self.r.is_zero() || !self.s.is_zero()
     }
     fn get_signature(&self) -> Result<Vec<u8>, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-
-        let r = string_to_vec_u8(&self.r);
-        let s = string_to_vec_u8(&self.s);
-
-        Ok([&r[..], &s[..]].concat())
+        Ok(join_signature(self.r, self.s))
     }
     fn get_recovery_id(&self) -> Result<u8, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-
-        let v = string_to_vec_u8(&self.v);
-
-        if v.is_empty() {
-            Ok(0 as u8)
-        } else {
-            Ok(1 as u8)
-        }
+        get_y_parity(self.v)
     }
     fn serialize(&self) -> Result<Vec<u8>, String> {
         let mut stream = rlp::RlpStream::new_list(11);
@@ -372,25 +345,24 @@ impl Sign for Transaction2930 {
         let gas_limit = u64_to_vec_u8(&self.gas_limit);
         stream.append(&gas_limit);
 
-        let to = string_to_vec_u8(&self.to[..]);
+        let to = encode_to(&self.to);
         stream.append(&to);
 
         let value = u256_to_vec_u8(&self.value);
         stream.append(&value);
 
-        let data = string_to_vec_u8(&self.data[..]);
-        stream.append(&data);
+        stream.append(&self.data.0);
 
         let access_list = encode_access_list(&self.access_list);
         stream.append_raw(&access_list[..], 1);
 
-        let v = string_to_vec_u8(&self.v[..]);
+        let v = u64_to_vec_u8(&self.v);
         stream.append(&v);
 
-        let r = string_to_vec_u8(&self.r[..]);
+        let r = u256_to_vec_u8(&self.r);
         stream.append(&r);
 
-        let s = string_to_vec_u8(&self.s[..]);
+        let s = u256_to_vec_u8(&self.s);
         stream.append(&s);
 
         let result = stream.out().to_vec();
@@ -400,76 +372,77 @@ impl Sign for Transaction2930 {
     fn get_nonce(&self) -> Result<u64, String> {
         Ok(self.nonce)
     }
+    fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+    fn clear_signature(&mut self) {
+        self.v = 0;
+        self.r = U256::zero();
+        self.s = U256::zero();
+    }
+    fn bump_fees(&mut self, percent: u64) -> Result<(), String> {
+        self.gas_price = bump_fee(self.gas_price, percent)?;
+        Ok(())
+    }
+    fn make_cancellation(&mut self, address: &Address) {
+        self.to = Some(*address);
+        self.value = U256::zero();
+        self.data = Bytes::default();
+        self.access_list = vec![];
+        self.gas_limit = CANCELLATION_GAS_LIMIT;
+    }
+    fn get_summary(&self) -> TransactionSummary {
+        TransactionSummary {
+            transaction_type: TransactionType::EIP2930,
+            chain_id: self.chain_id,
+            nonce: self.nonce,
+            to: self.to,
+            value: self.value.to_string(),
+            data: self.data.clone(),
+            gas_limit: self.gas_limit,
+            gas_price: Some(self.gas_price.to_string()),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            access_list: self.access_list.clone(),
+        }
+    }
 }
 
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Transaction1559 {
     pub chain_id: u64,
     pub nonce: u64,
     pub max_priority_fee_per_gas: U256,
     pub gas_limit: u64,
     pub max_fee_per_gas: U256,
-    pub to: String,
+    pub to: Option<Address>,
     pub value: U256,
-    pub data: String,
-    pub access_list: Vec<(String, Vec<String>)>,
-    pub v: String,
-    pub r: String,
-    pub s: String,
+    pub data: Bytes,
+    pub access_list: Vec<AccessListItem>,
+    pub v: u64,
+    pub r: U256,
+    pub s: U256,
 }
-impl From<Vec<u8>> for Transaction1559 {
-    fn from(data: Vec<u8>) -> Self {
-        let rlp = rlp::Rlp::new(&data[1..]);
-
-        let chain_id_hex = rlp.at(0).as_val::<Vec<u8>>();
-        let chain_id = vec_u8_to_u64(&chain_id_hex);
-
-        let nonce_hex = rlp.at(1).as_val::<Vec<u8>>();
-        let nonce = vec_u8_to_u64(&nonce_hex);
-
-        let max_priority_fee_per_gas_hex = rlp.at(2).as_val::<Vec<u8>>();
-        let max_priority_fee_per_gas = vec_u8_to_u256(&max_priority_fee_per_gas_hex);
-
-        let max_fee_per_gas_hex = rlp.at(3).as_val::<Vec<u8>>();
-
-        let max_fee_per_gas = vec_u8_to_u256(&max_fee_per_gas_hex);
-
-        let gas_limit_hex = rlp.at(4).as_val::<Vec<u8>>();
-        let gas_limit = vec_u8_to_u64(&gas_limit_hex);
-
-        let to_hex = rlp.at(5).as_val::<Vec<u8>>();
-        let to = vec_u8_to_string(&to_hex);
-
-        let value_hex = rlp.at(6).as_val::<Vec<u8>>();
-        let value = vec_u8_to_u256(&value_hex);
-
-        let data_tx_hex = rlp.at(7).as_val::<Vec<u8>>();
-        let data_tx = vec_u8_to_string(&data_tx_hex);
-
-        let access_list = decode_access_list(&rlp.at(8).as_raw().to_vec());
-
-        let v_hex = rlp.at(9).as_val::<Vec<u8>>();
-        let v = vec_u8_to_string(&v_hex);
-
-        let r_hex = rlp.at(10).as_val::<Vec<u8>>();
-        let r = vec_u8_to_string(&r_hex);
-
-        let s_hex = rlp.at(11).as_val::<Vec<u8>>();
-        let s = vec_u8_to_string(&s_hex);
-
-        Transaction1559 {
-            chain_id,
-            nonce,
-            max_priority_fee_per_gas,
-            max_fee_per_gas,
-            gas_limit,
-            to,
-            value,
-            data: data_tx,
-            access_list,
-            v,
-            r,
-            s,
-        }
+impl TryFrom<Vec<u8>> for Transaction1559 {
+    type Error = String;
+
+    fn try_from(data: Vec<u8>) -> Result<Self, Self::Error> {
+        let rlp = decode_list(data.get(1..).unwrap_or_default(), 12)?;
+
+        Ok(Transaction1559 {
+            chain_id: decode_u64(&rlp, 0)?,
+            nonce: decode_u64(&rlp, 1)?,
+            max_priority_fee_per_gas: decode_u256(&rlp, 2)?,
+            max_fee_per_gas: decode_u256(&rlp, 3)?,
+            gas_limit: decode_u64(&rlp, 4)?,
+            to: decode_to(&rlp, 5)?,
+            value: decode_u256(&rlp, 6)?,
+            data: Bytes(decode_bytes(&rlp, 7)?),
+            access_list: decode_access_list(&rlp, 8)?,
+            v: decode_u64(&rlp, 9)?,
+            r: decode_u256(&rlp, 10)?,
+            s: decode_u256(&rlp, 11)?,
+        })
     }
 }
 impl Sign for Transaction1559 {
@@ -481,14 +454,13 @@ impl Sign for Transaction1559 {
             u256_to_vec_u8(&self.max_priority_fee_per_gas),
             u256_to_vec_u8(&self.max_fee_per_gas),
             u64_to_vec_u8(&self.gas_limit),
-            string_to_vec_u8(&self.to),
+            encode_to(&self.to),
             u256_to_vec_u8(&self.value),
-            string_to_vec_u8(&self.data),
+            self.data.0.clone(),
         ];
 
-        for i in 0..=7 {
-            let item = &items[i];
-            stream.append(item);
+        for item in items {
+            stream.append(&item);
         }
 
         let access_list = encode_access_list(&self.access_list);
@@ -504,66 +476,31 @@ impl Sign for Transaction1559 {
         Ok(keccak256.to_vec())
     }
     fn sign(&mut self, signature: Vec<u8>, public_key: Vec<u8>) -> Result<Vec<u8>, String> {
-        let r_remove_leading_zeros = remove_leading(signature[..32].to_vec(), 0);
-        let s_remove_leading_zeros = remove_leading(signature[32..].to_vec(), 0);
-
-        let r = vec_u8_to_string(&r_remove_leading_zeros);
-
-        let s = vec_u8_to_string(&s_remove_leading_zeros);
+        let signature = normalize_signature(&signature)?;
 
-        let message = self.get_message_to_sign().unwrap();
-        let recovery_id = get_recovery_id(&message, &signature, &public_key).unwrap();
-        let v: String;
-        if recovery_id == 0 {
-            v = "".to_string();
-        } else {
-            v = "01".to_string();
-        }
+        let message = self.get_message_to_sign()?;
+        let recovery_id = get_recovery_id(&message, &signature, &public_key)?;
 
-        self.v = v;
-        self.r = r;
-        self.s = s;
+        self.v = u64::from(recovery_id);
+        self.r = U256::from_big_endian(&signature[..32]);
+        self.s = U256::from_big_endian(&signature[32..]);
 
-        let result = self.serialize().unwrap();
-        Ok(result)
+        self.serialize()
     }
     fn is_signed(&self) -> bool {
-        let r: String;
-        if self.r.starts_with("0x") {
-            r = self.r[2..].to_string();
-        } else {
-            r = self.r[..].to_string();
-        }
-        let s: String;
-        if self.s.starts_with("0x") {
-            s = self.s[2..].to_string();
-        } else {
-            s = self.s[..].to_string();
-        }
-
-        r != "00" || s != "00"
+        !self.r.is_zero() || !self.s.is_zero()
     }
     fn get_signature(&self) -> Result<Vec<u8>, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-
-        let r = string_to_vec_u8(&self.r);
-        let s = string_to_vec_u8(&self.s);
-
-        Ok([&r[..], &s[..]].concat())
+        Ok(join_signature(self.r, self.s))
     }
     fn get_recovery_id(&self) -> Result<u8, String> {
         if !self.is_signed() {
             return Err("This is not a signed transaction".to_string());
         }
-        let v = &self.v;
-
-        if v.is_empty() {
-            Ok(0 as u8)
-        } else {
-            Ok(1 as u8)
-        }
+        get_y_parity(self.v)
     }
     fn serialize(&self) -> Result<Vec<u8>, String> {
         let mut stream = rlp::RlpStream::new_list(12);
@@ -583,26 +520,25 @@ impl Sign for Transaction1559 {
         let gas_limit = u64_to_vec_u8(&self.gas_limit);
         stream.append(&gas_limit);
 
-        let to = string_to_vec_u8(&self.to[..]);
+        let to = encode_to(&self.to);
         stream.append(&to);
 
         let value = u256_to_vec_u8(&self.value);
         stream.append(&value);
 
-        let data = string_to_vec_u8(&self.data[..]);
-        stream.append(&data);
+        stream.append(&self.data.0);
 
         let access_list = encode_access_list(&self.access_list);
 
         stream.append_raw(&access_list[..], 1);
 
-        let v = string_to_vec_u8(&self.v[..]);
+        let v = u64_to_vec_u8(&self.v);
         stream.append(&v);
 
-        let r = string_to_vec_u8(&self.r[..]);
+        let r = u256_to_vec_u8(&self.r);
         stream.append(&r);
 
-        let s = string_to_vec_u8(&self.s[..]);
+        let s = u256_to_vec_u8(&self.s);
         stream.append(&s);
 
         let result = stream.out().to_vec();
@@ -612,24 +548,77 @@ impl Sign for Transaction1559 {
     fn get_nonce(&self) -> Result<u64, String> {
         Ok(self.nonce)
     }
+    fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+    fn clear_signature(&mut self) {
+        self.v = 0;
+        self.r = U256::zero();
+        self.s = U256::zero();
+    }
+    fn bump_fees(&mut self, percent: u64) -> Result<(), String> {
+        self.max_priority_fee_per_gas = bump_fee(self.max_priority_fee_per_gas, percent)?;
+        self.max_fee_per_gas = bump_fee(self.max_fee_per_gas, percent)?;
+        Ok(())
+    }
+    fn make_cancellation(&mut self, address: &Address) {
+        self.to = Some(*address);
+        self.value = U256::zero();
+        self.data = Bytes::default();
+        self.access_list = vec![];
+        self.gas_limit = CANCELLATION_GAS_LIMIT;
+    }
+    fn get_summary(&self) -> TransactionSummary {
+        TransactionSummary {
+            transaction_type: TransactionType::EIP1559,
+            chain_id: self.chain_id,
+            nonce: self.nonce,
+            to: self.to,
+            value: self.value.to_string(),
+            data: self.data.clone(),
+            gas_limit: self.gas_limit,
+            gas_price: None,
+            max_fee_per_gas: Some(self.max_fee_per_gas.to_string()),
+            max_priority_fee_per_gas: Some(self.max_priority_fee_per_gas.to_string()),
+            access_list: self.access_list.clone(),
+        }
+    }
 }
 
-pub fn get_transaction(hex_raw_tx: &Vec<u8>, chain_id: u64) -> Result<Box<dyn Sign>, String> {
-    let tx_type = get_transaction_type(hex_raw_tx).unwrap();
+pub fn get_transaction(hex_raw_tx: &[u8], chain_id: u64) -> Result<Box<dyn Sign>, String> {
+    let tx_type = get_transaction_type(hex_raw_tx)?;
 
-    if tx_type == TransactionType::Legacy {
-        Ok(Box::new(TransactionLegacy::from((hex_raw_tx.clone(), chain_id))))
-    } else if tx_type == TransactionType::EIP1559 {
-        Ok(Box::new(Transaction1559::from(hex_raw_tx.clone())))
-    } else if tx_type == TransactionType::EIP2930 {
-        Ok(Box::new(Transaction2930::from(hex_raw_tx.clone())))
-    } else {
-        Err(String::from("Invalid type"))
+    match tx_type {
+        TransactionType::Legacy => Ok(Box::new(TransactionLegacy::try_from((hex_raw_tx.to_vec(), chain_id))?)),
+        TransactionType::EIP1559 => Ok(Box::new(Transaction1559::try_from(hex_raw_tx.to_vec())?)),
+        TransactionType::EIP2930 => Ok(Box::new(Transaction2930::try_from(hex_raw_tx.to_vec())?)),
+    }
+}
+
+/// Returns the chain id encoded in a raw transaction. Typed transactions always
+/// carry one, legacy transactions only when they are EIP-155 signed or, unsigned,
+/// have the chain id in `v` as in the EIP-155 signing payload.
+pub fn get_chain_id(hex_raw_tx: &[u8]) -> Result<Option<u64>, String> {
+    match get_transaction_type(hex_raw_tx)? {
+        TransactionType::Legacy => {
+            let tx = TransactionLegacy::try_from((hex_raw_tx.to_vec(), 0))?;
+            match tx.v {
+                0 => Ok(None),
+                v if !tx.is_signed() => Ok(Some(v)),
+                27 | 28 => Ok(None),
+                v if v >= 35 => Ok(Some((v - 35) / 2)),
+                _ => Err("Invalid v".to_string()),
+            }
+        }
+        TransactionType::EIP2930 => Ok(Some(Transaction2930::try_from(hex_raw_tx.to_vec())?.chain_id)),
+        TransactionType::EIP1559 => Ok(Some(Transaction1559::try_from(hex_raw_tx.to_vec())?.chain_id)),
     }
 }
 
-fn get_transaction_type(hex_raw_tx: &Vec<u8>) -> Result<TransactionType, String> {
-    if hex_raw_tx[0] >= 0xc0 {
+pub fn get_transaction_type(hex_raw_tx: &[u8]) -> Result<TransactionType, String> {
+    if hex_raw_tx.is_empty() {
+        Err(String::from("Invalid type"))
+    } else if hex_raw_tx[0] >= 0xc0 {
         Ok(TransactionType::Legacy)
     } else if hex_raw_tx[0] == 0x01 {
         Ok(TransactionType::EIP2930)
@@ -640,11 +629,37 @@ fn get_transaction_type(hex_raw_tx: &Vec<u8>) -> Result<TransactionType, String>
     }
 }
 
-fn get_recovery_id(
-    message: &Vec<u8>,
-    signature: &Vec<u8>,
-    public_key: &Vec<u8>,
-) -> Result<u8, String> {
+/// Checks a 64-byte `r || s` signature and moves `s` to the lower half of the
+/// curve order, as Ethereum only accepts low-s signatures (EIP-2).
+pub fn normalize_signature(signature: &[u8]) -> Result<Vec<u8>, String> {
+    if signature.len() != 64 {
+        return Err("Invalid signature".to_string());
+    }
+    let mut signature =
+        libsecp256k1::Signature::parse_standard_slice(signature).map_err(|_| "Invalid signature".to_string())?;
+    if signature.r.is_zero() || signature.s.is_zero() {
+        return Err("Invalid signature".to_string());
+    }
+    signature.normalize_s();
+    Ok(signature.serialize().to_vec())
+}
+
+fn join_signature(r: U256, s: U256) -> Vec<u8> {
+    let mut signature = vec![0; 64];
+    r.to_big_endian(&mut signature[..32]);
+    s.to_big_endian(&mut signature[32..]);
+    signature
+}
+
+// Typed transactions store the recovery id itself as `v`.
+fn get_y_parity(v: u64) -> Result<u8, String> {
+    match v {
+        0 | 1 => Ok(v as u8),
+        _ => Err("Invalid v".to_string()),
+    }
+}
+
+pub(crate) fn get_recovery_id(message: &[u8], signature: &[u8], public_key: &[u8]) -> Result<u8, String> {
     if signature.len() != 64 {
         return Err("Invalid signature".to_string());
     }
@@ -664,27 +679,46 @@ fn get_recovery_id(
         let message_bytes: [u8; 32] = message[..].try_into().unwrap();
         let message_bytes_32 = libsecp256k1::Message::parse(&message_bytes);
 
-        let key = libsecp256k1::recover(&message_bytes_32, &signature_bytes_64, &recovery_id).unwrap();
-        if key.serialize_compressed() == public_key[..] {
-            return Ok(i as u8);
+        // Not every recovery id yields a point for a given signature.
+        if let Ok(key) = libsecp256k1::recover(&message_bytes_32, &signature_bytes_64, &recovery_id) {
+            if key.serialize_compressed() == public_key[..] {
+                return Ok(i as u8);
+            }
         }
     }
-    return Err("Not found".to_string());
+    return Err("Signature does not match public key".to_string());
 }
 
-fn encode_access_list(access_list: &Vec<(String, Vec<String>)>) -> Vec<u8> {
+const CANCELLATION_GAS_LIMIT: u64 = MIN_GAS_LIMIT;
+
+fn bump_fee(fee: U256, percent: u64) -> Result<U256, String> {
+    if percent < MIN_FEE_BUMP_PERCENT {
+        return Err(format!("Fee bump must be at least {}%", MIN_FEE_BUMP_PERCENT));
+    }
+    let scaled = fee
+        .checked_mul(U256::from(100 + percent))
+        .ok_or_else(|| "Fee overflow".to_string())?;
+
+    Ok((scaled + 99) / 100)
+}
+
+fn encode_to(to: &Option<Address>) -> Vec<u8> {
+    to.map(|to| to.0.to_vec()).unwrap_or_default()
+}
+
+fn encode_access_list(access_list: &[AccessListItem]) -> Vec<u8> {
     let mut stream = rlp::RlpStream::new_list(access_list.len());
 
-    for list in access_list {
+    for item in access_list {
         let mut stream_tuple = rlp::RlpStream::new_list(2);
 
         // append address
-        stream_tuple.append(&string_to_vec_u8(&list.0[..]));
+        stream_tuple.append(&item.address.0.to_vec());
 
         // append storage keys
-        let mut stream_storage_keys = rlp::RlpStream::new_list(list.1.len());
-        for storage_key in list.1.clone() {
-            stream_storage_keys.append(&string_to_vec_u8(&storage_key[..]));
+        let mut stream_storage_keys = rlp::RlpStream::new_list(item.storage_keys.len());
+        for storage_key in &item.storage_keys {
+            stream_storage_keys.append(&storage_key.0.to_vec());
         }
         stream_tuple.append_raw(&stream_storage_keys.out(), 1);
 
@@ -695,23 +729,68 @@ fn encode_access_list(access_list: &Vec<(String, Vec<String>)>) -> Vec<u8> {
     stream.out().to_vec()
 }
 
-fn decode_access_list(access_list: &Vec<u8>) -> Vec<(String, Vec<String>)> {
-    let mut decoded_access_list = vec![];
-    let rlp = rlp::Rlp::new(access_list);
-    for item in rlp.iter() {
-        let address = item.at(0).as_val();
-        let storage_keys_u8 = item.at(1).as_list::<Vec<u8>>();
-        let storage_keys = storage_keys_u8
+fn decode_access_list(rlp: &rlp::UntrustedRlp, index: usize) -> Result<Vec<AccessListItem>, String> {
+    let invalid = |_| "Invalid access list".to_string();
+    let mut access_list = vec![];
+
+    for item in rlp.at(index).map_err(invalid)?.iter() {
+        let address = item.val_at::<Vec<u8>>(0).map_err(invalid)?;
+        let storage_keys = item
+            .list_at::<Vec<u8>>(1)
+            .map_err(invalid)?
             .iter()
-            .map(|x| vec_u8_to_string(x))
-            .collect::<Vec<String>>();
-        decoded_access_list.push((vec_u8_to_string(&address), storage_keys));
+            .map(|key| H256::try_from(&key[..]))
+            .collect::<Result<Vec<H256>, String>>()?;
+
+        access_list.push(AccessListItem {
+            address: Address::try_from(&address[..])?,
+            storage_keys,
+        });
+    }
+    Ok(access_list)
+}
+
+fn decode_list(data: &[u8], item_count: usize) -> Result<rlp::UntrustedRlp<'_>, String> {
+    let rlp = rlp::UntrustedRlp::new(data);
+    match rlp.item_count() {
+        Ok(count) if rlp.is_list() && count == item_count => Ok(rlp),
+        _ => Err("Invalid transaction".to_string()),
     }
-    decoded_access_list
 }
+
+fn decode_bytes(rlp: &rlp::UntrustedRlp, index: usize) -> Result<Vec<u8>, String> {
+    rlp.val_at::<Vec<u8>>(index)
+        .map_err(|_| "Invalid transaction".to_string())
+}
+
+fn decode_u64(rlp: &rlp::UntrustedRlp, index: usize) -> Result<u64, String> {
+    let bytes = decode_bytes(rlp, index)?;
+    if bytes.len() > 8 {
+        return Err("Invalid transaction".to_string());
+    }
+    Ok(vec_u8_to_u64(&bytes))
+}
+
+fn decode_u256(rlp: &rlp::UntrustedRlp, index: usize) -> Result<U256, String> {
+    let bytes = decode_bytes(rlp, index)?;
+    if bytes.len() > 32 {
+        return Err("Invalid transaction".to_string());
+    }
+    Ok(U256::from_big_endian(&bytes))
+}
+
+fn decode_to(rlp: &rlp::UntrustedRlp, index: usize) -> Result<Option<Address>, String> {
+    let bytes = decode_bytes(rlp, index)?;
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    Address::try_from(&bytes[..]).map(Some)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::{string_to_vec_u8, vec_u8_to_string};
 
     #[test]
     fn get_recovery_id_valid() {
@@ -758,41 +837,182 @@ mod tests {
     }
 
     #[test]
-    fn access_list_encode() {
-        let expected = "f872f85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000000000000000000000000000000000000000000000000003a00000000000000000000000000000000000000000000000000000000000000007d694bb9bc244d798123fde783fcc1c72d3bb8c189413c0";
-        let address_1 = "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".to_string();
-        let storage_keys_1 = vec![
-            "0x0000000000000000000000000000000000000000000000000000000000000003".to_string(),
-            "0x0000000000000000000000000000000000000000000000000000000000000007".to_string(),
-        ];
+    fn bump_fee_rounds_up() {
+        assert_eq!(bump_fee(U256::from(1000), 10), Ok(U256::from(1100)));
+        assert_eq!(bump_fee(U256::from(15), 12), Ok(U256::from(17)));
+    }
+
+    #[test]
+    fn bump_fee_below_minimum() {
+        let expected = Err("Fee bump must be at least 10%".to_string());
+        assert_eq!(bump_fee(U256::from(1000), 5), expected);
+    }
+
+    // secp256k1 curve order
+    const N: &str = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141";
+
+    fn high_s_signature(signature: &[u8]) -> Vec<u8> {
+        let s = U256::from_big_endian(&string_to_vec_u8(N)) - U256::from_big_endian(&signature[32..]);
+        let mut high_s = [0; 32];
+        s.to_big_endian(&mut high_s);
+        [&signature[..32], &high_s[..]].concat()
+    }
+
+    fn unsigned_eip1559() -> Transaction1559 {
+        Transaction1559 {
+            chain_id: 1,
+            nonce: 0,
+            max_priority_fee_per_gas: U256::zero(),
+            gas_limit: 21_000,
+            max_fee_per_gas: U256::zero(),
+            to: Some("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalize_signature_flips_high_s() {
+        let signature = string_to_vec_u8("29edd4e1d65e1b778b464112d2febc6e97bb677aba5034408fd27b49921beca94c4e5b904d58553bcd9c788360e0bd55c513922cf1f33a6386033e886cd4f77f");
+        let high_s = high_s_signature(&signature);
+
+        assert_ne!(high_s, signature);
+        assert_eq!(normalize_signature(&high_s), Ok(signature.clone()));
+        assert_eq!(normalize_signature(&signature), Ok(signature));
+    }
+
+    #[test]
+    fn normalize_signature_rejects_malformed() {
+        let expected = Err("Invalid signature".to_string());
+        assert_eq!(normalize_signature(&[1; 63]), expected);
+        assert_eq!(normalize_signature(&[[0; 32], [1; 32]].concat()), expected);
+        assert_eq!(normalize_signature(&[[1; 32], [0; 32]].concat()), expected);
+        assert_eq!(normalize_signature(&[[1; 32], [0xff; 32]].concat()), expected);
+    }
+
+    #[test]
+    fn sign_normalizes_high_s() {
+        let secret_key = libsecp256k1::SecretKey::parse_slice(&[7; 32]).unwrap();
+        let public_key = libsecp256k1::PublicKey::from_secret_key(&secret_key).serialize_compressed().to_vec();
+        let address = crate::utils::get_address_from_public_key(public_key.clone()).unwrap();
+
+        let mut tx = unsigned_eip1559();
+        let message = libsecp256k1::Message::parse_slice(&tx.get_message_to_sign().unwrap()).unwrap();
+        let (signature, recovery_id) = libsecp256k1::sign(&message, &secret_key);
+        let signature = signature.serialize().to_vec();
 
-        let address_2 = "0xbb9bc244d798123fde783fcc1c72d3bb8c189413".to_string();
-        let storage_keys_2 = vec![];
+        let signed = tx.sign(high_s_signature(&signature), public_key).unwrap();
 
-        let access_list = vec![(address_1, storage_keys_1), (address_2, storage_keys_2)];
-        let encoded = encode_access_list(&access_list);
+        assert_eq!(tx.get_signature(), Ok(signature));
+        assert_eq!(tx.get_recovery_id(), Ok(recovery_id.serialize()));
+        assert_eq!(crate::verify::recover_transaction_signer(&signed), Ok(address));
+    }
+
+    #[test]
+    fn sign_with_other_public_key() {
+        let mut tx = unsigned_eip1559();
+        let signature = string_to_vec_u8("29edd4e1d65e1b778b464112d2febc6e97bb677aba5034408fd27b49921beca94c4e5b904d58553bcd9c788360e0bd55c513922cf1f33a6386033e886cd4f77f");
+        let public_key = string_to_vec_u8("02c397f23149d3464517d57b7cdc8e287428407f9beabfac731e7c24d536266cd1");
+
+        assert_eq!(
+            tx.sign(signature, public_key),
+            Err("Signature does not match public key".to_string())
+        );
+    }
+
+    #[test]
+    fn make_cancellation_eip1559() {
+        let mut tx = Transaction1559 {
+            chain_id: 1,
+            nonce: 7,
+            max_priority_fee_per_gas: U256::from(100),
+            gas_limit: 90_000,
+            max_fee_per_gas: U256::from(200),
+            to: Some("0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap()),
+            value: U256::from(5),
+            data: "0xa9059cbb".parse().unwrap(),
+            access_list: vec![],
+            v: 1,
+            r: U256::one(),
+            s: U256::one(),
+        };
+        let address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap();
+        tx.clear_signature();
+        tx.bump_fees(MIN_FEE_BUMP_PERCENT).unwrap();
+        tx.make_cancellation(&address);
+
+        assert!(!tx.is_signed());
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.max_priority_fee_per_gas, U256::from(110));
+        assert_eq!(tx.max_fee_per_gas, U256::from(220));
+        assert_eq!(tx.gas_limit, 21_000);
+        assert_eq!(tx.value, U256::zero());
+        assert_eq!(tx.to, Some(address));
+        assert!(tx.data.is_empty());
+    }
+
+    #[test]
+    fn access_list_encode() {
+        let expected = "f872f85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000000000000000000000000000000000000000000000000003a00000000000000000000000000000000000000000000000000000000000000007d694bb9bc244d798123fde783fcc1c72d3bb8c189413c0";
+        let encoded = encode_access_list(&access_list());
         assert_eq!(vec_u8_to_string(&encoded), expected)
     }
 
     #[test]
     fn access_list_decode() {
-        let expected: Vec<(String, Vec<String>)> = vec![
-            (
-                "de0b295669a9fd93d5f28d9ec85e40f4cb697bae".to_string(),
-                vec![
-                    "0000000000000000000000000000000000000000000000000000000000000003".to_string(),
-                    "0000000000000000000000000000000000000000000000000000000000000007".to_string(),
-                ],
-            ),
-            (
-                "bb9bc244d798123fde783fcc1c72d3bb8c189413".to_string(),
-                vec![],
-            ),
-        ];
-        let access_list = "f872f85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000000000000000000000000000000000000000000000000003a00000000000000000000000000000000000000000000000000000000000000007d694bb9bc244d798123fde783fcc1c72d3bb8c189413c0";
-        let access_list_hex = string_to_vec_u8(&access_list);
+        let access_list_hex = string_to_vec_u8("f872f85994de0b295669a9fd93d5f28d9ec85e40f4cb697baef842a00000000000000000000000000000000000000000000000000000000000000003a00000000000000000000000000000000000000000000000000000000000000007d694bb9bc244d798123fde783fcc1c72d3bb8c189413c0");
+
+        // decode_access_list reads an item of a list
+        let mut stream = rlp::RlpStream::new_list(1);
+        stream.append_raw(&access_list_hex, 1);
+        let list = stream.out();
+
+        let decoded = decode_access_list(&rlp::UntrustedRlp::new(&list), 0);
+        assert_eq!(decoded, Ok(access_list()));
+    }
 
-        let decoded = decode_access_list(&access_list_hex);
-        assert_eq!(decoded, expected);
+    #[test]
+    fn access_list_decode_invalid_storage_key() {
+        let mut stream = rlp::RlpStream::new_list(1);
+        stream.begin_list(1);
+        stream.begin_list(2);
+        stream.append(&vec![1u8; 20]);
+        stream.begin_list(1);
+        stream.append(&vec![1u8; 31]);
+        let list = stream.out();
+
+        let decoded = decode_access_list(&rlp::UntrustedRlp::new(&list), 0);
+        assert_eq!(decoded, Err("Invalid hash".to_string()));
+    }
+
+    #[test]
+    fn get_transaction_malformed() {
+        let expected = Some("Invalid transaction".to_string());
+        assert_eq!(get_transaction(&[0x02, 0xc0], 1).err(), expected);
+        assert_eq!(get_transaction(&[0x02], 1).err(), expected);
+        assert_eq!(get_transaction(&[0xc1, 0x80], 1).err(), expected);
+
+        let mut tx = unsigned_eip1559().serialize().unwrap();
+        // shorten `to` to 19 bytes
+        let position = tx.iter().position(|&b| b == 0x94).unwrap();
+        tx[position] = 0x93;
+        tx.remove(position + 1);
+        tx[1] -= 1;
+        assert_eq!(get_transaction(&tx, 1).err(), Some("Invalid address".to_string()));
+    }
+
+    fn access_list() -> Vec<AccessListItem> {
+        vec![
+            AccessListItem {
+                address: "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae".parse().unwrap(),
+                storage_keys: vec![
+                    "0x0000000000000000000000000000000000000000000000000000000000000003".parse().unwrap(),
+                    "0x0000000000000000000000000000000000000000000000000000000000000007".parse().unwrap(),
+                ],
+            },
+            AccessListItem {
+                address: "0xbb9bc244d798123fde783fcc1c72d3bb8c189413".parse().unwrap(),
+                storage_keys: vec![],
+            },
+        ]
     }
 }
@@ -0,0 +1,202 @@
+use crate::utils::vec_u8_to_string;
+use ic_cdk::export::candid::types::{Serializer, Type};
+use ic_cdk::export::candid::CandidType;
+use ic_cdk::export::serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// 20-byte account address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Address(pub [u8; 20]);
+
+/// 32-byte hash or storage key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct H256(pub [u8; 32]);
+
+/// Arbitrary byte string such as calldata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+/// Entry of an EIP-2930 access list.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<H256>,
+}
+
+impl Address {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl H256 {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Bytes {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl TryFrom<&[u8]> for Address {
+    type Error = String;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes.try_into().map(Address).map_err(|_| "Invalid address".to_string())
+    }
+}
+
+impl TryFrom<&[u8]> for H256 {
+    type Error = String;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        bytes.try_into().map(H256).map_err(|_| "Invalid hash".to_string())
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Bytes(bytes)
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode_hex(s).map_err(|_| "Invalid address".to_string())?;
+        Address::try_from(&bytes[..])
+    }
+}
+
+impl FromStr for H256 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode_hex(s).map_err(|_| "Invalid hash".to_string())?;
+        H256::try_from(&bytes[..])
+    }
+}
+
+impl FromStr for Bytes {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode_hex(s).map(Bytes)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", vec_u8_to_string(&self.0.to_vec()))
+    }
+}
+
+impl fmt::Display for H256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", vec_u8_to_string(&self.0.to_vec()))
+    }
+}
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", vec_u8_to_string(&self.0))
+    }
+}
+
+// The types are encoded as 0x-prefixed hex text in Candid and serde, so
+// interfaces that used `String` for them stay compatible.
+macro_rules! impl_hex_text {
+    ($($name:ident),*) => {$(
+        impl CandidType for $name {
+            fn _ty() -> Type {
+                Type::Text
+            }
+
+            fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+                serializer.serialize_text(&self.to_string())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: ic_cdk::export::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+impl_hex_text!(Address, H256, Bytes);
+
+// Decodes hex with an optional `0x` prefix.
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return Err("Invalid hex".to_string());
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| "Invalid hex".to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_round_trip() {
+        let address: Address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap();
+        assert_eq!(address.to_string(), "0x907dc4d0be5d691970cae886fcab34ed65a2cd66");
+        assert_eq!("907dc4d0be5d691970cae886fcab34ed65a2cd66".parse(), Ok(address));
+    }
+
+    #[test]
+    fn address_invalid() {
+        let expected = Err("Invalid address".to_string());
+        assert_eq!("0x907dc4d0be5d691970cae886fcab34ed65a2cd".parse::<Address>(), expected);
+        assert_eq!("0x907dc4d0be5d691970cae886fcab34ed65a2cd6".parse::<Address>(), expected);
+        assert_eq!("0x907dc4d0be5d691970cae886fcab34ed65a2cdzz".parse::<Address>(), expected);
+    }
+
+    #[test]
+    fn bytes_parse() {
+        assert_eq!("0x".parse(), Ok(Bytes(vec![])));
+        assert_eq!("0xa9059cbb".parse(), Ok(Bytes(vec![0xa9, 0x05, 0x9c, 0xbb])));
+        assert_eq!("0xa90".parse::<Bytes>(), Err("Invalid hex".to_string()));
+        assert_eq!("0xé0".parse::<Bytes>(), Err("Invalid hex".to_string()));
+    }
+
+    #[test]
+    fn address_candid_is_text() {
+        use ic_cdk::export::candid::{Decode, Encode};
+
+        let address: Address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap();
+        let encoded = Encode!(&address).unwrap();
+        assert_eq!(encoded, Encode!(&address.to_string()).unwrap());
+        assert_eq!(Decode!(&encoded, Address).unwrap(), address);
+
+        let invalid = Encode!(&"0x00".to_string()).unwrap();
+        assert!(Decode!(&invalid, Address).is_err());
+    }
+
+    #[test]
+    fn h256_invalid() {
+        assert_eq!("0x01".parse::<H256>(), Err("Invalid hash".to_string()));
+    }
+}
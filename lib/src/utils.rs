@@ -1,12 +1,13 @@
 use easy_hasher::easy_hasher;
 use ic_cdk::export::Principal;
+use crate::types::Address;
 use primitive_types::U256;
 
 pub fn get_derivation_path(caller: Principal) -> Vec<u8> {
     caller.as_slice().to_vec()
 }
 
-pub fn get_address_from_public_key(public_key: Vec<u8>) -> Result<String, String> {
+pub fn get_address_from_public_key(public_key: Vec<u8>) -> Result<Address, String> {
     if public_key.len() != 33 {
         return Err("Invalid length of public key".to_string());
     }
@@ -17,34 +18,61 @@ pub fn get_address_from_public_key(public_key: Vec<u8>) -> Result<String, String
         .serialize();
 
     let keccak256 = easy_hasher::raw_keccak256(pub_key[1..].to_vec());
-    let keccak256_hex = keccak256.to_hex_string();
-    let address: String = "0x".to_owned() + &keccak256_hex[24..];
+    Address::try_from(&keccak256.to_vec()[12..])
+}
 
-    Ok(address)
+pub fn get_transaction_hash(signed_tx: &[u8]) -> Vec<u8> {
+    easy_hasher::raw_keccak256(signed_tx.to_vec()).to_vec()
 }
 
-pub fn get_transfer_data(address: &str, amount: U256) -> Result<String, String> {
-    if address.len() != 42 {
-        return Err("Invalid address".to_string());
+/// Address of a contract deployed with CREATE by `sender` at `nonce`.
+pub fn get_create_address(sender: &Address, nonce: u64) -> Address {
+    let mut stream = rlp::RlpStream::new_list(2);
+    stream.append(&sender.0.to_vec());
+    stream.append(&nonce);
+
+    let keccak256 = easy_hasher::raw_keccak256(stream.out().to_vec());
+    Address::try_from(&keccak256.to_vec()[12..]).unwrap()
+}
+
+/// Address of a contract deployed with CREATE2 by `deployer`.
+pub fn get_create2_address(deployer: &Address, salt: &[u8], init_code_hash: &[u8]) -> Result<Address, String> {
+    if salt.len() != 32 {
+        return Err("Invalid salt".to_string());
+    }
+    if init_code_hash.len() != 32 {
+        return Err("Invalid init code hash".to_string());
     }
+
+    let mut data = vec![0xff];
+    data.extend(deployer.as_bytes());
+    data.extend(salt);
+    data.extend(init_code_hash);
+
+    let keccak256 = easy_hasher::raw_keccak256(data);
+    Address::try_from(&keccak256.to_vec()[12..])
+}
+
+pub fn get_transfer_data(address: &Address, amount: U256) -> Vec<u8> {
     let method_sig = "transfer(address,uint256)";
     let keccak256 = easy_hasher::raw_keccak256(method_sig.as_bytes().to_vec());
-    let method_id = &keccak256.to_hex_string()[..8];
 
-    let address_64 = format!("{:0>64}", &address[2..]);
+    let mut amount_bytes = [0; 32];
+    amount.to_big_endian(&mut amount_bytes);
 
-    let mut bytes = [0; 32];
-    amount.to_big_endian(&mut bytes);
-    let amount_hex = bytes
-        .into_iter()
-        .map(|x| format!("{:02x}", x))
-        .collect::<Vec<String>>()
-        .concat();
-    let amount_64 = format!("{:0>64}", amount_hex);
+    [&keccak256.to_vec()[..4], &[0; 12], address.as_bytes(), &amount_bytes].concat()
+}
+
+pub fn get_balance_of_data(address: &Address) -> Vec<u8> {
+    let method_sig = "balanceOf(address)";
+    let keccak256 = easy_hasher::raw_keccak256(method_sig.as_bytes().to_vec());
 
-    Ok(method_id.to_owned() + &address_64 + &amount_64)
+    [&keccak256.to_vec()[..4], &[0; 12], address.as_bytes()].concat()
 }
 
+/// Test helper for hex literals. It panics on invalid hex, so production code
+/// parses hex with the types in [`crate::types`] instead.
+#[cfg(test)]
 pub fn string_to_vec_u8(str: &str) -> Vec<u8> {
     let starts_from: usize;
     if str.starts_with("0x") {
@@ -59,12 +87,6 @@ pub fn string_to_vec_u8(str: &str) -> Vec<u8> {
         .collect::<Vec<u8>>()
 }
 
-pub fn remove_leading(vec: Vec<u8>, element: u8) -> Vec<u8> {
-    let start = vec.iter().position(|&x| x != element).unwrap();
-    let result = &vec[start..];
-    result.to_vec()
-}
-
 pub fn u64_to_vec_u8(u: &u64) -> Vec<u8> {
     u.to_be_bytes()
         .into_iter()
@@ -100,6 +122,18 @@ pub fn u64_to_u256(value: u64) -> U256 {
     vec_u8_to_u256(&u64_to_vec_u8(&value))
 }
 
+/// Checks that a provider set can reach `threshold` agreeing responses.
+pub fn check_rpc_providers(providers: &[String], threshold: u64) -> Result<(), String> {
+    if providers.is_empty() {
+        return Err("at least one RPC provider is required".to_string());
+    }
+    if threshold == 0 || threshold > providers.len() as u64 {
+        return Err(format!("RPC threshold must be between 1 and {}", providers.len()));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,7 +144,7 @@ mod tests {
         let public_key_str = "02c397f23149d3464517d57b7cdc8e287428407f9beabfac731e7c24d536266cd1";
         let public_key_to_vec = string_to_vec_u8(&public_key_str);
         let result = get_address_from_public_key(public_key_to_vec).unwrap();
-        assert_eq!(result, expected);
+        assert_eq!(result.to_string(), expected);
     }
 
     #[test]
@@ -135,19 +169,59 @@ mod tests {
     fn get_transfer_data_valid() {
         let expected ="a9059cbb000000000000000000000000907dc4d0be5d691970cae886fcab34ed65a2cd660000000000000000000000000000000000000000000000000000000000000001";
 
-        let address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66";
+        let address = "0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap();
         let amount = primitive_types::U256::one();
 
-        let result = get_transfer_data(address, amount).unwrap();
-        assert_eq!(result, expected);
+        let result = get_transfer_data(&address, amount);
+        assert_eq!(vec_u8_to_string(&result), expected);
+    }
+
+    #[test]
+    fn get_create_address_valid() {
+        let sender = "0x6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap();
+        assert_eq!(get_create_address(&sender, 0).to_string(), "0xcd234a471b72ba2f1ccf0a70fcaba648a5eecd8d");
+        assert_eq!(get_create_address(&sender, 1).to_string(), "0x343c43a37d37dff08ae8c4a11544c718abb4fcf8");
+    }
+
+    #[test]
+    fn get_create2_address_valid() {
+        // Example 1 of EIP-1014
+        let init_code_hash = easy_hasher::raw_keccak256(vec![0x00]).to_vec();
+        let result = get_create2_address(&Address::default(), &[0; 32], &init_code_hash);
+        assert_eq!(result.unwrap().to_string(), "0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38");
+    }
+
+    #[test]
+    fn get_create2_address_invalid_salt() {
+        let result = get_create2_address(&Address::default(), &[0; 31], &[0; 32]);
+        assert_eq!(result, Err("Invalid salt".to_string()));
+    }
+
+    #[test]
+    fn get_balance_of_data_valid() {
+        let expected = "70a08231000000000000000000000000907dc4d0be5d691970cae886fcab34ed65a2cd66";
+        let result = get_balance_of_data(&"0x907dc4d0be5d691970cae886fcab34ed65a2cd66".parse().unwrap());
+        assert_eq!(vec_u8_to_string(&result), expected);
+    }
+
+    #[test]
+    fn check_rpc_providers_threshold() {
+        let providers = vec!["https://a.example.com".to_string(), "https://b.example.com".to_string()];
+        assert_eq!(check_rpc_providers(&providers, 2), Ok(()));
+        assert_eq!(
+            check_rpc_providers(&providers, 3),
+            Err("RPC threshold must be between 1 and 2".to_string())
+        );
+        assert_eq!(
+            check_rpc_providers(&[], 1),
+            Err("at least one RPC provider is required".to_string())
+        );
     }
 
     #[test]
     fn get_transfer_data_with_invalid_address() {
         let expected = Err("Invalid address".to_string());
-        let address = "0x00";
-        let value = primitive_types::U256::one();
-        let result = get_transfer_data(address, value);
+        let result = "0x00".parse::<Address>();
         assert_eq!(result, expected);
     }
 }
@@ -0,0 +1,179 @@
+use crate::transaction::{get_transaction, normalize_signature, Sign, TransactionLegacy};
+use crate::types::Address;
+use crate::utils::get_address_from_public_key;
+use easy_hasher::easy_hasher;
+use ic_cdk::export::Principal;
+use libsecp256k1::{Message, RecoveryId, Signature};
+
+/// Recovers the address that produced a 64-byte `r || s` signature of
+/// `message_hash`. `recovery_id` is 0 or 1.
+pub fn recover_address(message_hash: &[u8], signature: &[u8], recovery_id: u8) -> Result<Address, String> {
+    if signature.len() != 64 {
+        return Err("Invalid signature".to_string());
+    }
+    let message = Message::parse_slice(message_hash).map_err(|_| "Invalid message".to_string())?;
+    let signature = Signature::parse_standard_slice(signature).map_err(|_| "Invalid signature".to_string())?;
+    let recovery_id = RecoveryId::parse(recovery_id).map_err(|_| "Invalid recovery id".to_string())?;
+
+    let public_key =
+        libsecp256k1::recover(&message, &signature, &recovery_id).map_err(|_| "Invalid signature".to_string())?;
+
+    get_address_from_public_key(public_key.serialize_compressed().to_vec())
+}
+
+/// Recovers the sender of a signed legacy, EIP-2930 or EIP-1559 transaction.
+/// Legacy transactions must be EIP-155 protected and, as in Ethereum, high-s
+/// signatures are rejected.
+pub fn recover_transaction_signer(signed_tx: &[u8]) -> Result<Address, String> {
+    recover_transaction_signer_with_options(signed_tx, false)
+}
+
+/// Same as [`recover_transaction_signer`], but with `pre_eip155` also accepts
+/// legacy transactions signed without replay protection.
+pub fn recover_transaction_signer_with_options(signed_tx: &[u8], pre_eip155: bool) -> Result<Address, String> {
+    let chain_id = match signed_tx.first() {
+        Some(byte) if *byte >= 0xc0 => get_legacy_chain_id(signed_tx, pre_eip155)?,
+        _ => 0,
+    };
+    let tx = get_transaction(signed_tx, chain_id)?;
+    recover_signer(tx.as_ref())
+}
+
+// Does not check replay protection. Callers reject legacy transactions without
+// it unless the user opted in.
+pub(crate) fn recover_signer(tx: &dyn Sign) -> Result<Address, String> {
+    let signature = tx.get_signature()?;
+    if normalize_signature(&signature)? != signature {
+        return Err("Signature s value is too high".to_string());
+    }
+    let recovery_id = tx.get_recovery_id()?;
+    let message_hash = tx.get_message_to_sign()?;
+
+    recover_address(&message_hash, &signature, recovery_id)
+}
+
+/// Hash signed by `personal_sign` (EIP-191 version 0x45).
+pub fn hash_personal_message(message: &[u8]) -> Vec<u8> {
+    let prefix = format!("\x19Ethereum Signed Message:\n{}", message.len());
+    easy_hasher::raw_keccak256([prefix.as_bytes(), message].concat()).to_vec()
+}
+
+/// Recovers the signer of a 65-byte `personal_sign` signature of `message`.
+pub fn recover_personal_sign(message: &[u8], signature: &[u8]) -> Result<Address, String> {
+    let (signature, recovery_id) = split_signature(signature)?;
+    recover_address(&hash_personal_message(message), signature, recovery_id)
+}
+
+/// EIP-712 digest of a struct given its domain separator and struct hash.
+pub fn hash_typed_data(domain_separator: &[u8], struct_hash: &[u8]) -> Result<Vec<u8>, String> {
+    if domain_separator.len() != 32 {
+        return Err("Invalid domain separator".to_string());
+    }
+    if struct_hash.len() != 32 {
+        return Err("Invalid struct hash".to_string());
+    }
+    Ok(easy_hasher::raw_keccak256([&[0x19, 0x01], domain_separator, struct_hash].concat()).to_vec())
+}
+
+/// Recovers the signer of a 65-byte `eth_signTypedData` signature.
+pub fn recover_typed_data(domain_separator: &[u8], struct_hash: &[u8], signature: &[u8]) -> Result<Address, String> {
+    let digest = hash_typed_data(domain_separator, struct_hash)?;
+    let (signature, recovery_id) = split_signature(signature)?;
+    recover_address(&digest, signature, recovery_id)
+}
+
+/// Checks that `signed_tx` was signed with the address of `principal_id`.
+pub fn verify_transaction(principal_id: Principal, signed_tx: &[u8]) -> Result<bool, String> {
+    let address = crate::get_address(principal_id)?;
+    let signer = recover_transaction_signer(signed_tx)?;
+    Ok(signer == address)
+}
+
+// Splits an `r || s || v` signature, accepting v as 0/1 or 27/28.
+fn split_signature(signature: &[u8]) -> Result<(&[u8], u8), String> {
+    if signature.len() != 65 {
+        return Err("Invalid signature".to_string());
+    }
+    let recovery_id = match signature[64] {
+        v @ (0 | 1) => v,
+        v @ (27 | 28) => v - 27,
+        _ => return Err("Invalid recovery id".to_string()),
+    };
+    Ok((&signature[..64], recovery_id))
+}
+
+fn get_legacy_chain_id(signed_tx: &[u8], pre_eip155: bool) -> Result<u64, String> {
+    let tx = TransactionLegacy::try_from((signed_tx.to_vec(), 0))?;
+    match tx.v {
+        v if v >= 35 => Ok((v - 35) / 2),
+        27 | 28 if pre_eip155 => Ok(0),
+        _ => Err("Transaction is not EIP-155 protected".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{string_to_vec_u8, vec_u8_to_string};
+    use libsecp256k1::{PublicKey, SecretKey};
+
+    const PRIVATE_KEY: &str = "5c86d3784f39013aa50aada6d97f9bad733636d57bf6bb18b0bca1ffcff374b4";
+
+    fn sign(message_hash: &[u8]) -> (Vec<u8>, Address) {
+        let secret_key = SecretKey::parse_slice(&string_to_vec_u8(PRIVATE_KEY)).unwrap();
+        let (signature, recovery_id) = libsecp256k1::sign(&Message::parse_slice(message_hash).unwrap(), &secret_key);
+        let address = get_address_from_public_key(
+            PublicKey::from_secret_key(&secret_key).serialize_compressed().to_vec(),
+        )
+        .unwrap();
+        let signature = [&signature.serialize()[..], &[recovery_id.serialize() + 27]].concat();
+        (signature, address)
+    }
+
+    #[test]
+    fn hash_personal_message_known_vector() {
+        assert_eq!(
+            vec_u8_to_string(&hash_personal_message(b"hello")),
+            "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750"
+        );
+    }
+
+    #[test]
+    fn recover_personal_sign_valid() {
+        let (signature, address) = sign(&hash_personal_message(b"hello"));
+        assert_eq!(recover_personal_sign(b"hello", &signature), Ok(address));
+        assert_ne!(recover_personal_sign(b"hell0", &signature), Ok(address));
+    }
+
+    #[test]
+    fn recover_personal_sign_invalid_recovery_id() {
+        let (mut signature, _) = sign(&hash_personal_message(b"hello"));
+        signature[64] = 29;
+        assert_eq!(recover_personal_sign(b"hello", &signature), Err("Invalid recovery id".to_string()));
+        assert_eq!(recover_personal_sign(b"hello", &signature[..64]), Err("Invalid signature".to_string()));
+    }
+
+    #[test]
+    fn recover_typed_data_valid() {
+        let domain_separator = [1; 32];
+        let struct_hash = [2; 32];
+        let digest = hash_typed_data(&domain_separator, &struct_hash).unwrap();
+        let (signature, address) = sign(&digest);
+
+        assert_eq!(recover_typed_data(&domain_separator, &struct_hash, &signature), Ok(address));
+        assert_eq!(
+            hash_typed_data(&domain_separator, &[2; 31]),
+            Err("Invalid struct hash".to_string())
+        );
+    }
+
+    #[test]
+    fn recover_transaction_signer_requires_eip155() {
+        let signed_tx = string_to_vec_u8("f85f800182520894095e7baea6a9c7c4c2dfeb977efac326af552d870a801ba048b55bfa915ac795c431978d8a6a992b628d557da5ff759b307d495a36649353a01fffd310ac743f371de3b9f7f9cb56c0b28ad43601b4ab949f53faa07bd2c804");
+        assert_eq!(
+            recover_transaction_signer(&signed_tx),
+            Err("Transaction is not EIP-155 protected".to_string())
+        );
+        assert!(recover_transaction_signer_with_options(&signed_tx, true).is_ok());
+    }
+}
@@ -0,0 +1,49 @@
+// Runs the library the way a downstream canister's unit tests would: from
+// outside the crate, with only the `testing` feature standing in for the IC.
+#![cfg(feature = "testing")]
+
+use futures::executor::block_on;
+use ic_cdk::export::Principal;
+use ic_evm_sign::builder::TransactionBuilder;
+use ic_evm_sign::state::Environment;
+use ic_evm_sign::testing::MockManagementCanister;
+use ic_evm_sign::types::Address;
+use ic_evm_sign::verify;
+use primitive_types::U256;
+
+#[test]
+fn sign_transaction_with_mock_management_canister() {
+    let mock = MockManagementCanister::default().install();
+    ic_evm_sign::init(Some(Environment::Staging));
+    let principal_id = Principal::from_text("aaaaa-aa").unwrap();
+    ic_evm_sign::init_controller(principal_id);
+
+    let address = block_on(ic_evm_sign::create_address(principal_id)).unwrap().address;
+    mock.runtime().attach_cycles(20_000_000_000);
+    assert_eq!(block_on(ic_evm_sign::deposit_cycles(principal_id)), Ok(20_000_000_000));
+
+    mock.runtime().set_time(1_000);
+    let raw_tx = TransactionBuilder::new(1)
+        .nonce(0)
+        .to(Address::default())
+        .value(U256::from(1))
+        .gas_limit(21_000)
+        .max_fee_per_gas(U256::from(100))
+        .max_priority_fee_per_gas(U256::from(1))
+        .build_raw()
+        .unwrap();
+    let res = block_on(ic_evm_sign::sign_transaction(raw_tx, 1, principal_id)).unwrap();
+
+    assert_eq!(verify::recover_transaction_signer(&res.sign_tx), Ok(address));
+    assert_eq!(mock.calls().last().unwrap().method, "sign_with_ecdsa");
+    assert_eq!(ic_evm_sign::get_cycles_balance(principal_id), 10_000_000_000);
+
+    let user = ic_evm_sign::get_caller_data(principal_id, 1).unwrap();
+    assert_eq!(user.transactions.transactions[0].timestamp, 1_000);
+    let log = ic_evm_sign::get_audit_log(principal_id, 0, 10).unwrap();
+    assert_eq!(log.total, 3);
+
+    ic_evm_sign::pre_upgrade();
+    ic_evm_sign::post_upgrade();
+    assert_eq!(ic_evm_sign::get_audit_log(principal_id, 0, 10).unwrap().total, 3);
+}